@@ -0,0 +1,81 @@
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Generates the character name table used by `\N{...}` escapes from the
+/// vendored `data/UnicodeData.txt` and `data/NameAliases.txt`.
+fn main() {
+    println!("cargo:rerun-if-changed=data/UnicodeData.txt");
+    println!("cargo:rerun-if-changed=data/NameAliases.txt");
+
+    let data = File::open("data/UnicodeData.txt")
+        .expect("data/UnicodeData.txt is missing");
+
+    let mut names = Vec::new();
+    let mut ideographs = Vec::new();
+    let mut range_start = None;
+
+    for line in BufReader::new(data).lines() {
+        let line = line.expect("data/UnicodeData.txt isn't valid UTF-8");
+        let mut fields = line.split(';');
+        let code = fields.next().and_then(|code| u32::from_str_radix(code, 16).ok());
+        let (code, name) = match (code, fields.next()) {
+            (Some(code), Some(name)) => (code, name),
+            _ => panic!("Malformed line in UnicodeData.txt: {}", line),
+        };
+
+        // Large blocks like the CJK ideographs are given as a range, their
+        // names are the block's prefix followed by the code point.
+        if name.ends_with(", First>") {
+            range_start = Some(code);
+        } else if name.ends_with(", Last>") {
+            let first = range_start.take().expect("Range without a start");
+            if name.starts_with("<CJK Ideograph") {
+                ideographs.push(("CJK UNIFIED IDEOGRAPH-", first, code));
+            } else if name.starts_with("<Tangut Ideograph") {
+                ideographs.push(("TANGUT IDEOGRAPH-", first, code));
+            }
+        } else if !name.starts_with('<') {
+            names.push((name.to_owned(), code));
+        }
+    }
+
+    // Aliases such as `LINE FEED` and `BEL` are looked up like any other
+    // name.
+    let aliases = File::open("data/NameAliases.txt")
+        .expect("data/NameAliases.txt is missing");
+
+    for line in BufReader::new(aliases).lines() {
+        let line = line.expect("data/NameAliases.txt isn't valid UTF-8");
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(';');
+        let code = fields.next().and_then(|code| u32::from_str_radix(code, 16).ok());
+        match (code, fields.next()) {
+            (Some(code), Some(alias)) => names.push((alias.to_owned(), code)),
+            _ => panic!("Malformed line in NameAliases.txt: {}", line),
+        }
+    }
+
+    names.sort();
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("unicode_names.rs");
+    let mut out = BufWriter::new(File::create(&path).unwrap());
+
+    writeln!(out, "// Generated by build.rs from data/UnicodeData.txt and data/NameAliases.txt.")
+        .unwrap();
+    writeln!(out, "static NAMES: &'static [(&'static str, u32)] = &[").unwrap();
+    for &(ref name, code) in &names {
+        writeln!(out, "    ({:?}, 0x{:X}),", name, code).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    writeln!(out, "static IDEOGRAPHS: &'static [(&'static str, u32, u32)] = &[").unwrap();
+    for &(prefix, first, last) in &ideographs {
+        writeln!(out, "    ({:?}, 0x{:X}, 0x{:X}),", prefix, first, last).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+}
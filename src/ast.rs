@@ -1,5 +1,7 @@
-use lexeme::{Lexeme, Operator, Keyword};
-use parser::ParseError;
+use std::borrow::Cow;
+
+use lexeme::{Conversion, Lexeme, Operator};
+use span::Span;
 
 #[derive(Clone, Debug)]
 pub enum Ast<'a> {
@@ -10,9 +12,21 @@ pub enum Ast<'a> {
 #[derive(Clone, Debug)]
 pub enum Expression<'a> {
     Await(Box<Expression<'a>>),
-    Comparison(Box<Comparison<'a>>),
+    /// `and` or `or` between two or more values, which are evaluated left to
+    /// right until one decides the result.
+    BoolOp(BoolOperator, Vec<Expression<'a>>),
+    Compare(Box<Compare<'a>>),
+    /// `body if test else orelse`.
+    Conditional(Box<Expression<'a>>, Box<Expression<'a>>, Box<Expression<'a>>),
+    Lambda(Box<Parameters<'a>>, Box<Expression<'a>>),
+    /// `name := value`.
+    NamedExpr(Name<'a>, Box<Expression<'a>>),
+    Not(Box<Expression<'a>>),
     Operation(Box<Expression<'a>>, Operator, Option<Box<Expression<'a>>>),
     Primary(Primary<'a>),
+    /// `*iterable`, in a display or the targets of an assignment.
+    Starred(Box<Expression<'a>>),
+    Tuple(Vec<Expression<'a>>),
 }
 
 impl<'a> Expression<'a> {
@@ -25,82 +39,325 @@ impl<'a> Expression<'a> {
     {
         Expression::Operation(Box::new(lhs), op, Some(Box::new(rhs)))
     }
+}
 
-    pub fn is_number(&self) -> bool {
-        match *self {
-            Expression::Primary(Primary::Atom(Atom::Literal(_))) => {
-                true
-            }
-            _ => false,
-        }
-    }
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BoolOperator {
+    And,
+    Or,
 }
 
+/// A chain of comparisons like `a < b <= c`, where `ops[i]` compares the
+/// value before `comparators[i]` with it.
 #[derive(Clone, Debug)]
-pub enum Comparison<'a> {
-    Op(Expression<'a>, Operator, Expression<'a>),
-    Keyword(Expression<'a>, Keyword, Expression<'a>),
-    Truthy(Expression<'a>),
-    Notty(Expression<'a>),
+pub struct Compare<'a> {
+    pub comparators: Vec<Expression<'a>>,
+    pub left: Expression<'a>,
+    pub ops: Vec<CompareOp>,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompareOp {
+    Equals,
+    In,
+    Is,
+    IsNot,
+    LessThan,
+    LessThanEqual,
+    MoreThan,
+    MoreThanEqual,
+    NotEquals,
+    NotIn,
+}
+
+impl CompareOp {
+    pub fn from_operator(operator: Operator) -> Option<Self> {
+        match operator {
+            Operator::Equals => Some(CompareOp::Equals),
+            Operator::LessThan => Some(CompareOp::LessThan),
+            Operator::LessThanEqual => Some(CompareOp::LessThanEqual),
+            Operator::MoreThan => Some(CompareOp::MoreThan),
+            Operator::MoreThanEqual => Some(CompareOp::MoreThanEqual),
+            Operator::NotEquals => Some(CompareOp::NotEquals),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum Atom<'a> {
-    Identifier(usize, &'a str),
+    Comprehension(Span, Box<Comprehension<'a>>),
+    Dict(Span, Vec<DictItem<'a>>),
+    Formatted(Span, Vec<FormattedPart<'a>>),
+    Identifier(Span, Cow<'a, str>),
+    List(Span, Vec<Expression<'a>>),
     Literal(Lexeme<'a>),
-    Yield(Box<Expression<'a>>),
+    Set(Span, Vec<Expression<'a>>),
+    /// `yield`, with the values yielded if there are any.
+    Yield(Option<Box<Expression<'a>>>),
+    /// `yield from` an iterable.
+    YieldFrom(Box<Expression<'a>>),
+}
+
+#[derive(Clone, Debug)]
+pub enum DictItem<'a> {
+    /// `**mapping`
+    DoubleStarred(Expression<'a>),
+    Pair(Expression<'a>, Expression<'a>),
+}
+
+/// The element of a comprehension, and the `for` clauses producing it.
+#[derive(Clone, Debug)]
+pub enum Comprehension<'a> {
+    Dict(Expression<'a>, Expression<'a>, Vec<ComprehensionFor<'a>>),
+    Generator(Expression<'a>, Vec<ComprehensionFor<'a>>),
+    List(Expression<'a>, Vec<ComprehensionFor<'a>>),
+    Set(Expression<'a>, Vec<ComprehensionFor<'a>>),
+}
+
+impl<'a> Comprehension<'a> {
+    /// The key and value of a dict comprehension, or the element of the
+    /// others.
+    pub fn elements(&self) -> Vec<&Expression<'a>> {
+        match *self {
+            Comprehension::Dict(ref key, ref value, _) => vec![key, value],
+            Comprehension::Generator(ref element, _) |
+            Comprehension::List(ref element, _) |
+            Comprehension::Set(ref element, _) => vec![element],
+        }
+    }
+
+    pub fn clauses(&self) -> &[ComprehensionFor<'a>] {
+        match *self {
+            Comprehension::Dict(_, _, ref clauses) |
+            Comprehension::Generator(_, ref clauses) |
+            Comprehension::List(_, ref clauses) |
+            Comprehension::Set(_, ref clauses) => clauses,
+        }
+    }
+}
+
+/// `for target in iterable`, with the `if` conditions that follow it.
+#[derive(Clone, Debug)]
+pub struct ComprehensionFor<'a> {
+    pub conditions: Vec<Expression<'a>>,
+    pub is_async: bool,
+    pub iterable: Expression<'a>,
+    pub span: Span,
+    pub target: Expression<'a>,
+}
+
+/// A piece of an f-string, either literal text or a replacement field with
+/// its conversion and format spec.
+#[derive(Clone, Debug)]
+pub enum FormattedPart<'a> {
+    Literal(String),
+    Field(Box<Expression<'a>>, Option<Conversion>, Vec<FormattedPart<'a>>),
 }
 
 #[derive(Clone, Debug)]
 pub enum Primary<'a> {
     Atom(Atom<'a>),
-    AttributeRef(Box<Primary<'a>>, Box<Primary<'a>>),
-    Subscription(Box<Primary<'a>>, ()),
-    Slice(Never),
-    Call(Box<Primary<'a>>, ()),
+    AttributeRef(Box<Expression<'a>>, Name<'a>),
+    Subscription(Box<Expression<'a>>, Vec<Slice<'a>>),
+    Call(Box<Expression<'a>>, Vec<Argument<'a>>),
 }
 
 impl<'a> Primary<'a> {
-    pub fn new_call(expr: Expression<'a>, arg_list: ()) -> Self {
-        unimplemented!()
+    pub fn new_call(expr: Expression<'a>, arg_list: Vec<Argument<'a>>) -> Self {
+        Primary::Call(Box::new(expr), arg_list)
     }
 
-    pub fn new_subscription(expr: Expression<'a>, sub_list: ()) -> Self {
-        unimplemented!()
+    pub fn new_subscription(expr: Expression<'a>, sub_list: Vec<Slice<'a>>) -> Self {
+        Primary::Subscription(Box::new(expr), sub_list)
     }
 
-    pub fn new_attribute_ref(lhs: Expression<'a>, rhs: Expression<'a>)
-        -> Result<Self, ParseError<'a>>
-    {
-        match (lhs, rhs) {
-            (Expression::Primary(lhs), Expression::Primary(rhs)) => {
-                Ok(Primary::AttributeRef(Box::new(lhs), Box::new(rhs)))
-            }
-            _ => panic!("Not a primary")
-        }
+    pub fn new_attribute_ref(lhs: Expression<'a>, name: Name<'a>) -> Self {
+        Primary::AttributeRef(Box::new(lhs), name)
     }
 }
 
+/// One of the comma separated subscripts between the brackets of a
+/// subscription. When there are no ranges, comma separated indexes are a
+/// single index of a tuple.
+#[derive(Clone, Debug)]
+pub enum Slice<'a> {
+    Index(Expression<'a>),
+    /// `lower:upper:step`, any of which can be left out.
+    Range(Option<Expression<'a>>, Option<Expression<'a>>, Option<Expression<'a>>),
+}
+
 #[derive(Clone, Debug)]
 pub enum Statement<'a> {
-    Assignment(Expression<'a>, Expression<'a>),
+    /// `target: annotation = value`, where the value is optional.
+    AnnAssign(Expression<'a>, Expression<'a>, Option<Expression<'a>>),
+    Assert(Span, Expression<'a>, Option<Expression<'a>>),
+    /// `targets[0] = targets[1] = ... = value`.
+    Assignment(Vec<Expression<'a>>, Expression<'a>),
+    /// `target op= value`, holding the binary operator, like `Add` for `+=`.
+    AugAssign(Expression<'a>, Operator, Expression<'a>),
+    Break(Span),
+    Compound(CompoundStatement<'a>),
+    Continue(Span),
+    Delete(Span, Vec<Expression<'a>>),
+    Expression(Expression<'a>),
+    Global(Span, Vec<Name<'a>>),
+    Import(Span, Vec<Alias<'a>>),
+    ImportFrom(Span, ImportFrom<'a>),
+    NonLocal(Span, Vec<Name<'a>>),
+    Pass(Span),
+    /// `raise exception from cause`.
+    Raise(Span, Option<Expression<'a>>, Option<Expression<'a>>),
+    Return(Span, Option<Expression<'a>>),
 }
 
-/*
-pub enum CompoundStatement {
-    If(If),
+/// The statements in the block of a compound statement.
+pub type Suite<'a> = Vec<Statement<'a>>;
+
+#[derive(Clone, Debug)]
+pub enum CompoundStatement<'a> {
+    ClassDef(ClassDef<'a>),
+    For(For<'a>),
+    FunctionDef(FunctionDef<'a>),
+    If(If<'a>),
+    Try(Try<'a>),
+    While(While<'a>),
+    With(With<'a>),
 }
-*/
 
 #[derive(Clone, Debug)]
-enum Never{}
-/*
-pub struct If {
-    expression: Expression,
-    elif_cases: Option<Vec<(Expression, Suite)>>,
-    else_case: Option<(Expression, Suite)>,
+pub struct ClassDef<'a> {
+    pub arguments: Vec<Argument<'a>>,
+    pub body: Suite<'a>,
+    pub decorators: Vec<Expression<'a>>,
+    pub name: Name<'a>,
+    pub span: Span,
 }
 
-pub struct Star(Expression);
-*/
+#[derive(Clone, Debug)]
+pub struct FunctionDef<'a> {
+    pub body: Suite<'a>,
+    pub decorators: Vec<Expression<'a>>,
+    pub is_async: bool,
+    pub name: Name<'a>,
+    pub parameters: Parameters<'a>,
+    pub returns: Option<Expression<'a>>,
+    pub span: Span,
+}
+
+/// The parameters of a function, split by where they can be passed: before
+/// the `/`, before the `*` or `*args`, and after it.
+#[derive(Clone, Debug, Default)]
+pub struct Parameters<'a> {
+    pub keyword_only: Vec<Parameter<'a>>,
+    pub kwargs: Option<Parameter<'a>>,
+    pub positional: Vec<Parameter<'a>>,
+    pub positional_only: Vec<Parameter<'a>>,
+    pub varargs: Option<Parameter<'a>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Parameter<'a> {
+    pub annotation: Option<Expression<'a>>,
+    pub default: Option<Expression<'a>>,
+    pub name: Name<'a>,
+}
+
+/// An argument in a call or a class's bases.
+#[derive(Clone, Debug)]
+pub enum Argument<'a> {
+    /// `**mapping`
+    DoubleStarred(Expression<'a>),
+    /// `name=value`
+    Keyword(Name<'a>, Expression<'a>),
+    Positional(Expression<'a>),
+    /// `*iterable`
+    Starred(Expression<'a>),
+}
+
+#[derive(Clone, Debug)]
+pub struct For<'a> {
+    pub body: Suite<'a>,
+    pub else_case: Option<Suite<'a>>,
+    pub is_async: bool,
+    pub iterable: Expression<'a>,
+    pub span: Span,
+    pub target: Expression<'a>,
+}
+
+#[derive(Clone, Debug)]
+pub struct If<'a> {
+    pub body: Suite<'a>,
+    pub elif_cases: Vec<(Expression<'a>, Suite<'a>)>,
+    pub else_case: Option<Suite<'a>>,
+    pub expression: Expression<'a>,
+    pub span: Span,
+}
+
+/// `try` with its `except` clauses, which are all `except*` if `star` is
+/// set.
+#[derive(Clone, Debug)]
+pub struct Try<'a> {
+    pub body: Suite<'a>,
+    pub else_case: Option<Suite<'a>>,
+    pub finally: Option<Suite<'a>>,
+    pub handlers: Vec<ExceptHandler<'a>>,
+    pub span: Span,
+    pub star: bool,
+}
+
+/// `except expression as name:`, where both are optional for a bare
+/// `except:`.
+#[derive(Clone, Debug)]
+pub struct ExceptHandler<'a> {
+    pub body: Suite<'a>,
+    pub expression: Option<Expression<'a>>,
+    pub name: Option<Name<'a>>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct While<'a> {
+    pub body: Suite<'a>,
+    pub else_case: Option<Suite<'a>>,
+    pub expression: Expression<'a>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct With<'a> {
+    pub body: Suite<'a>,
+    pub is_async: bool,
+    pub items: Vec<WithItem<'a>>,
+    pub span: Span,
+}
+
+/// A context manager, and the target after `as` if there is one.
+#[derive(Clone, Debug)]
+pub struct WithItem<'a> {
+    pub expression: Expression<'a>,
+    pub target: Option<Expression<'a>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Name<'a> {
+    pub name: Cow<'a, str>,
+    pub span: Span,
+}
+
+/// A dotted module path, or a name imported from one, and what it's bound
+/// to if that's given with `as`.
+#[derive(Clone, Debug)]
+pub struct Alias<'a> {
+    pub alias: Option<Name<'a>>,
+    pub path: Vec<Name<'a>>,
+}
+
+/// `from module import names`. `level` counts the leading dots of a relative
+/// import, and `names` is `None` for `import *`.
+#[derive(Clone, Debug)]
+pub struct ImportFrom<'a> {
+    pub level: usize,
+    pub module: Vec<Name<'a>>,
+    pub names: Option<Vec<Alias<'a>>>,
+}
@@ -0,0 +1,247 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str;
+
+use span::Span;
+use self::EncodingError::*;
+
+const BOM: &'static [u8] = b"\xEF\xBB\xBF";
+
+/// The encodings a source file can declare.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Latin1,
+    Utf8,
+}
+
+impl Encoding {
+    /// Looks up an encoding by the name given in a coding declaration,
+    /// accepting the same spellings as Python for the ones we support.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase().replace('_', "-");
+        let is = |base: &str| name == base || name.starts_with(&format!("{}-", base));
+
+        if is("utf-8") || name == "utf8" {
+            Some(Encoding::Utf8)
+        } else if is("latin-1") || is("iso-8859-1") || is("iso-latin-1") ||
+                  name == "latin1" || name == "iso8859-1" || name == "l1" {
+            Some(Encoding::Latin1)
+        } else if name == "ascii" || name == "us-ascii" {
+            Some(Encoding::Ascii)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Ascii => "ascii",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Utf8 => "utf-8",
+        }
+    }
+}
+
+/// Decodes a source file as described by PEP 263. A UTF-8 byte order mark
+/// is removed, and a `coding` declaration in a comment on either of the
+/// first two lines picks the encoding. Without either the source must be
+/// UTF-8. Spans in errors are byte offsets into `bytes`.
+pub fn decode<'a>(bytes: &'a [u8]) -> Result<Cow<'a, str>, EncodingError> {
+    let encoding = detect(bytes)?;
+    let start = if has_bom(bytes) { BOM.len() } else { 0 };
+    let source = &bytes[start..];
+    match encoding {
+        Encoding::Utf8 => match str::from_utf8(source) {
+            Ok(source) => Ok(Cow::Borrowed(source)),
+            Err(error) => {
+                let invalid = start + error.valid_up_to();
+                let length = error.error_len().unwrap_or(source.len() + start - invalid);
+                Err(InvalidBytes(Span::new(invalid, invalid + length), encoding))
+            }
+        },
+        Encoding::Ascii => match source.iter().position(|&byte| byte > 0x7F) {
+            Some(index) => Err(InvalidBytes(Span::new(start + index, start + index + 1),
+                                            encoding)),
+            None => Ok(Cow::Borrowed(str::from_utf8(source).unwrap())),
+        },
+        Encoding::Latin1 => Ok(Cow::Owned(source.iter().map(|&byte| byte as char).collect())),
+    }
+}
+
+/// The encoding `decode` will use for `bytes`, without decoding them.
+pub fn detect(bytes: &[u8]) -> Result<Encoding, EncodingError> {
+    let bom = has_bom(bytes);
+    let start = if bom { BOM.len() } else { 0 };
+
+    match declaration(bytes, start) {
+        Some(span) => {
+            let name = String::from_utf8_lossy(&bytes[span.start..span.end]).into_owned();
+            match Encoding::from_name(&name) {
+                Some(Encoding::Utf8) => Ok(Encoding::Utf8),
+                Some(_) if bom => Err(EncodingWithBom(span, name)),
+                Some(encoding) => Ok(encoding),
+                None => Err(UnknownEncoding(span, name)),
+            }
+        }
+        None => Ok(Encoding::Utf8),
+    }
+}
+
+pub fn has_bom(bytes: &[u8]) -> bool {
+    bytes.starts_with(BOM)
+}
+
+/// Finds the encoding name in a declaration like `# -*- coding: latin-1 -*-`
+/// on the first line, or on the second if the first is only a comment or
+/// blank.
+fn declaration(bytes: &[u8], start: usize) -> Option<Span> {
+    let mut line_start = start;
+
+    for _ in 0..2 {
+        let line_end = bytes[line_start..].iter()
+                                          .position(|&byte| byte == b'\n' || byte == b'\r')
+                                          .map_or(bytes.len(), |end| line_start + end);
+        let line = &bytes[line_start..line_end];
+        let text = line.iter()
+                       .position(|&byte| byte != b' ' && byte != b'\t' && byte != b'\x0C')
+                       .map(|indent| &line[indent..]);
+
+        match text {
+            Some(text) if text[0] == b'#' => {}
+            Some(_) => return None,
+            None => {}
+        }
+
+        if let Some(span) = coding_name(line) {
+            return Some(Span::new(line_start + span.start, line_start + span.end));
+        }
+
+        line_start = match bytes[line_end..].first() {
+            Some(&b'\r') if bytes.get(line_end + 1) == Some(&b'\n') => line_end + 2,
+            Some(_) => line_end + 1,
+            None => return None,
+        };
+    }
+
+    None
+}
+
+/// Matches `coding[:=][ \t]*([-\w.]+)` within a comment line.
+fn coding_name(line: &[u8]) -> Option<Span> {
+    let comment = line.iter().position(|&byte| byte == b'#')?;
+    let mut index = comment;
+
+    while let Some(found) = find(&line[index..], b"coding") {
+        index += found + b"coding".len();
+
+        match line.get(index) {
+            Some(&b':') | Some(&b'=') => {}
+            _ => continue,
+        }
+
+        let mut name = index + 1;
+        while let Some(&b' ') | Some(&b'\t') = line.get(name) {
+            name += 1;
+        }
+
+        let end = line[name..].iter()
+                              .position(|&byte| !(byte.is_ascii_alphanumeric() ||
+                                                  byte == b'-' || byte == b'_' ||
+                                                  byte == b'.'))
+                              .map_or(line.len(), |end| name + end);
+        if end > name {
+            return Some(Span::new(name, end));
+        }
+    }
+
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncodingError {
+    EncodingWithBom(Span, String),
+    InvalidBytes(Span, Encoding),
+    UnknownEncoding(Span, String),
+}
+
+impl EncodingError {
+    pub fn span(&self) -> Span {
+        match *self {
+            EncodingWithBom(span, _) |
+            InvalidBytes(span, _) |
+            UnknownEncoding(span, _) => span,
+        }
+    }
+
+    /// The line, starting at one, that the error is on in `bytes`.
+    pub fn line(&self, bytes: &[u8]) -> usize {
+        let before = &bytes[..self.span().start.min(bytes.len())];
+        let crlf = find_all(before, b"\r\n");
+        let newlines = before.iter().filter(|&&byte| byte == b'\n' || byte == b'\r').count();
+        newlines - crlf + 1
+    }
+}
+
+fn find_all(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack.windows(needle.len()).filter(|&window| window == needle).count()
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodingWithBom(_, ref name) => write!(f, "encoding problem: {} with BOM", name),
+            InvalidBytes(_, encoding) => {
+                write!(f, "source is not valid {}, see PEP 263 for details", encoding.name())
+            }
+            UnknownEncoding(_, ref name) => write!(f, "unknown encoding: {}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declarations() {
+        assert_eq!(decode(b"x = 'caf\xC3\xA9'\n").unwrap(), "x = 'caf\u{E9}'\n");
+        assert_eq!(decode(b"\xEF\xBB\xBFx = 1\n").unwrap(), "x = 1\n");
+        assert_eq!(decode(b"# -*- coding: latin-1 -*-\nx = 'caf\xE9'\n").unwrap(),
+                   "# -*- coding: latin-1 -*-\nx = 'caf\u{E9}'\n");
+        assert_eq!(decode(b"#!/usr/bin/env python\r\n# vim: set fileencoding=iso-8859-1 :\r\n\xE9").unwrap(),
+                   "#!/usr/bin/env python\r\n# vim: set fileencoding=iso-8859-1 :\r\n\u{E9}");
+        assert_eq!(decode(b"\xEF\xBB\xBF# coding=utf_8\n").unwrap(), "# coding=utf_8\n");
+        // Only a comment can declare an encoding, and only on the first two
+        // lines.
+        assert!(decode(b"x = 1  # coding: latin-1\n\xE9").is_err());
+        assert!(decode(b"x = 1\n# coding: latin-1\n\xE9").is_err());
+        assert!(decode(b"\n\n# coding: latin-1\n\xE9").is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let source = b"# coding: klingon\n";
+        let error = decode(source).unwrap_err();
+        assert_eq!(error, UnknownEncoding(Span::new(10, 17), String::from("klingon")));
+        assert_eq!(error.to_string(), "unknown encoding: klingon");
+
+        let source = b"\xEF\xBB\xBF# coding: latin-1\n";
+        assert_eq!(decode(source).unwrap_err().to_string(),
+                   "encoding problem: latin-1 with BOM");
+
+        let source = b"a = 1\r\nb = '\xE9'\n";
+        let error = decode(source).unwrap_err();
+        assert_eq!(error, InvalidBytes(Span::new(12, 13), Encoding::Utf8));
+        assert_eq!(error.line(source), 2);
+        assert_eq!(error.to_string(), "source is not valid utf-8, see PEP 263 for details");
+
+        let source = b"# coding: ascii\n\xC3\xA9";
+        assert_eq!(decode(source).unwrap_err(),
+                   InvalidBytes(Span::new(16, 17), Encoding::Ascii));
+    }
+}
@@ -1,29 +1,120 @@
+use std::borrow::Cow;
+
+use span::Span;
+use types::Int;
+
 #[derive(Clone, Debug)]
 pub enum Lexeme<'a> {
-    Bytes(usize, Vec<u8>),
-    Dedent,
-    Delimiter(usize, Delimiter),
-    Float(f64),
-    Identifier(usize, &'a str),
-    Indent,
-    Integer(i64),
-    Keyword(usize, Keyword),
-    Newline,
-    Operator(Operator),
-    Str(usize, String),
+    Bytes(Span, Vec<u8>),
+    Dedent(Span),
+    Delimiter(Span, Delimiter),
+    /// The end of the source. Only the lossless token stream yields this, to
+    /// hold the trivia after the last lexeme.
+    EndMarker(Span),
+    Float(Span, f64),
+    FormattedStr(Span, Vec<FormatPart<'a>>),
+    /// An identifier, normalized to NFKC as Python requires.
+    Identifier(Span, Cow<'a, str>),
+    Imaginary(Span, f64),
+    Indent(Span),
+    Integer(Span, Int),
+    Keyword(Span, Keyword),
+    Newline(Span),
+    Operator(Span, Operator),
+    Str(Span, String),
 }
 
 impl<'a> Lexeme<'a> {
-    pub fn is_unary(&self) -> bool {
+    pub fn span(&self) -> Span {
         match *self {
-            Lexeme::Operator(Operator::UnaryAdd) |
-            Lexeme::Operator(Operator::UnarySub) |
-            Lexeme::Operator(Operator::UnaryNot) => true,
-            _ => false,
+            Lexeme::Bytes(span, _) |
+            Lexeme::Dedent(span) |
+            Lexeme::Delimiter(span, _) |
+            Lexeme::EndMarker(span) |
+            Lexeme::Float(span, _) |
+            Lexeme::FormattedStr(span, _) |
+            Lexeme::Identifier(span, _) |
+            Lexeme::Imaginary(span, _) |
+            Lexeme::Indent(span) |
+            Lexeme::Integer(span, _) |
+            Lexeme::Keyword(span, _) |
+            Lexeme::Newline(span) |
+            Lexeme::Operator(span, _) |
+            Lexeme::Str(span, _) => span,
+        }
+    }
+
+    /// The same lexeme for its text having moved `delta` bytes along, into
+    /// `source`. Used to keep lexemes from before an edit.
+    pub fn moved<'b>(&self, delta: isize, source: &'b str) -> Lexeme<'b> {
+        let span = self.span().shift(delta);
+        match *self {
+            Lexeme::Bytes(_, ref bytes) => Lexeme::Bytes(span, bytes.clone()),
+            Lexeme::Dedent(_) => Lexeme::Dedent(span),
+            Lexeme::Delimiter(_, delimiter) => Lexeme::Delimiter(span, delimiter),
+            Lexeme::EndMarker(_) => Lexeme::EndMarker(span),
+            Lexeme::Float(_, value) => Lexeme::Float(span, value),
+            Lexeme::FormattedStr(_, ref parts) => {
+                Lexeme::FormattedStr(span, moved_parts(parts, delta, source))
+            }
+            Lexeme::Identifier(_, Cow::Borrowed(_)) => {
+                Lexeme::Identifier(span, Cow::Borrowed(&source[span.start..span.end]))
+            }
+            Lexeme::Identifier(_, Cow::Owned(ref name)) => {
+                Lexeme::Identifier(span, Cow::Owned(name.clone()))
+            }
+            Lexeme::Imaginary(_, value) => Lexeme::Imaginary(span, value),
+            Lexeme::Indent(_) => Lexeme::Indent(span),
+            Lexeme::Integer(_, ref value) => Lexeme::Integer(span, value.clone()),
+            Lexeme::Keyword(_, keyword) => Lexeme::Keyword(span, keyword),
+            Lexeme::Newline(_) => Lexeme::Newline(span),
+            Lexeme::Operator(_, operator) => Lexeme::Operator(span, operator),
+            Lexeme::Str(_, ref string) => Lexeme::Str(span, string.clone()),
         }
     }
 }
 
+fn moved_parts<'b>(parts: &[FormatPart], delta: isize, source: &'b str) -> Vec<FormatPart<'b>> {
+    parts.iter()
+         .map(|part| match *part {
+             FormatPart::Literal(ref string) => FormatPart::Literal(string.clone()),
+             FormatPart::Field(ref field) => {
+                 let expression = field.expression.shift(delta);
+                 FormatPart::Field(FormatField {
+                     expression: expression,
+                     source: &source[expression.start..expression.end],
+                     conversion: field.conversion,
+                     format_spec: moved_parts(&field.format_spec, delta, source),
+                 })
+             }
+         })
+         .collect()
+}
+
+/// A piece of an f-string. Replacement fields hold the source of their
+/// expression, which is left for the parser.
+#[derive(Clone, Debug)]
+pub enum FormatPart<'a> {
+    Literal(String),
+    Field(FormatField<'a>),
+}
+
+#[derive(Clone, Debug)]
+pub struct FormatField<'a> {
+    pub expression: Span,
+    pub source: &'a str,
+    pub conversion: Option<Conversion>,
+    pub format_spec: Vec<FormatPart<'a>>,
+}
+
+/// The `!a`, `!r` and `!s` conversions of a replacement field.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Conversion {
+    Ascii,
+    Repr,
+    Str,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Delimiter {
     DictClose,
@@ -90,6 +181,7 @@ pub enum Operator {
     DecAssign,
     Div,
     DivAssign,
+    Ellipsis,
     Equals,
     FuncAnno,
     LessThan,
@@ -119,6 +211,7 @@ pub enum Operator {
     UnaryAdd,
     UnarySub,
     UnaryNot,
+    Walrus,
     Xor,
     XorAssign,
 }
@@ -133,18 +226,6 @@ impl Operator {
             _ => false,
         }
     }
-    // comp_op: '<'|'>'|'=='|'>='|'<='|'<>'|'!='
-    pub fn is_comp_op(self) -> bool {
-        match self {
-            Operator::LessThan      |
-            Operator::MoreThan      |
-            Operator::Equals        |
-            Operator::LessThanEqual |
-            Operator::MoreThanEqual |
-            Operator::NotEquals     => true,
-            _ => false,
-        }
-    }
 
     pub fn is_operator(string: &str) -> Option<Self> {
         use self::Operator::*;
@@ -161,6 +242,7 @@ impl Operator {
             "," => Some(Sep),
             "/" => Some(Div),
             "/=" => Some(DivAssign),
+            "..." => Some(Ellipsis),
             "==" => Some(Equals),
             "->" => Some(FuncAnno),
             "<" => Some(LessThan),
@@ -186,6 +268,7 @@ impl Operator {
             "-" => Some(Sub),
             "-=" => Some(SubAssign),
             ";" => Some(Term),
+            ":=" => Some(Walrus),
             "^" => Some(Xor),
             "^=" => Some(XorAssign),
             _ => None,
@@ -239,6 +322,7 @@ pub enum Keyword {
     And,
     As,
     Assert,
+    Async,
     Await,
     Break,
     Class,
@@ -257,12 +341,10 @@ pub enum Keyword {
     Import,
     In,
     Is,
-    IsNot,
     Lambda,
     NonLocal,
     None,
     Not,
-    NotIn,
     Or,
     Pass,
     Raise,
@@ -283,6 +365,7 @@ impl Keyword {
             "and" => Some(Keyword::And),
             "as" => Some(Keyword::As),
             "assert" => Some(Keyword::Assert),
+            "async" => Some(Keyword::Async),
             "await" => Some(Keyword::Await),
             "break" => Some(Keyword::Break),
             "class" => Some(Keyword::Class),
@@ -314,11 +397,4 @@ impl Keyword {
             _ => None,
         }
     }
-    // 'in'|'not' 'in'|'is'|'is' 'not'
-    pub fn is_comp_keyword(self) -> bool {
-        match self {
-            Keyword::In | Keyword::Not | Keyword::Is => true,
-            _ => false
-        }
-    }
 }
@@ -1,168 +1,283 @@
+use std::borrow::Cow;
 use std::char;
+use std::collections::VecDeque;
 use std::fmt;
+use std::mem;
 use std::ops;
 use std::str::CharIndices;
 use itertools::multipeek;
 use itertools::structs::MultiPeek;
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
 
 pub type LexResult<'a> = Result<Lexeme<'a>, LexerError<'a>>;
 
 use symbols::*;
 use lexeme::*;
+use span::Span;
+use types::Int;
+use unicode_names;
 use self::LexerError::*;
 
-macro_rules! get_or_eof {
-    ($next:expr) => {
-        match $next {
-            Some(tuple) => tuple,
-            None => return Err(Eof),
-        }
-    }
+const NO_PREFIX: [Prefix; 2] = [Prefix::Ignore, Prefix::Ignore];
+/// The length of the longest operators, like `**=` and `...`.
+const MAX_OPERATOR_LEN: usize = 3;
+
+/// How the string currently being lexed is quoted.
+#[derive(Clone, Copy)]
+struct Quotes {
+    quote: char,
+    triple: bool,
+    raw: bool,
 }
 
+/// Lexes a source one logical line at a time, yielding its lexemes and any
+/// errors as an iterator. Errors are yielded ahead of the line they were
+/// found on, and lexing carries on past them.
 pub struct Lexer<'a> {
+    /// Added to every span, for lexers over a slice of a larger source.
+    base: usize,
+    errors: Vec<LexerError<'a>>,
+    /// Set once the end of the source has been reached.
+    finished: bool,
+    /// Lex as if inside brackets, with no indentation or newlines.
+    in_brackets: bool,
+    /// The columns of the enclosing blocks, as given by `lex_indentation`.
+    indents: Vec<(usize, usize)>,
     iter: MultiPeek<CharIndices<'a>>,
-    output: Vec<Lexeme<'a>>,
-    stack: Vec<u8>,
+    /// Byte offset just past the last character consumed.
+    offset: usize,
+    /// The rest of the current line, waiting to be yielded.
+    pending: VecDeque<LexResult<'a>>,
+    /// How many of `errors` have been moved into `pending`.
+    reported: usize,
     source: &'a str,
 }
 
 impl<'a> Lexer<'a> {
 
     pub fn new(source: &'a str) -> Self {
-        Lexer {
-            iter: multipeek(source.char_indices()),
-            output: Vec::new(),
-            stack: Vec::new(),
-            source: source,
-        }
+        Lexer::with_offset(source, 0)
     }
 
-    pub fn lex(&mut self) {
-        loop {
-            match self.lex_line() {
-                Err(LexerError::Continue) => continue,
-                Err(LexerError::Eof) => break,
-                Err(error) => panic!("{:?}", error),
-                Ok((indent, lex)) => {
-                    self.output.extend(lex);
-                    self.output.push(Lexeme::Newline);
-                    self.stack.push(indent);
-                },
-            }
-        }
+    /// Carries on lexing `source` from `offset`, the start of a logical line,
+    /// inside the blocks indented to `indents`.
+    pub fn resume(source: &'a str, offset: usize, indents: Vec<(usize, usize)>) -> Self {
+        let mut lexer = Lexer::with_offset(&source[offset..], offset);
+        lexer.indents = indents;
+        lexer
+    }
+
+    /// Like `with_offset`, but lexes `source` as if it were inside brackets,
+    /// so whitespace and newlines between lexemes are ignored and it's all one
+    /// line. Used for the expressions in f-strings.
+    pub fn in_brackets(source: &'a str, offset: usize) -> Self {
+        let mut lexer = Lexer::with_offset(source, offset);
+        lexer.in_brackets = true;
+        lexer
+    }
+
+    /// The columns of the blocks enclosing the line being lexed, starting
+    /// with the outermost, as `indentation` gives them.
+    pub fn indents(&self) -> &[(usize, usize)] {
+        &self.indents
     }
 
-    pub fn output(self) -> Vec<Lexeme<'a>> {
-        self.output
+    /// Lexes `source` as if it started `offset` bytes into a larger file, so
+    /// that spans point into that file.
+    pub fn with_offset(source: &'a str, offset: usize) -> Self {
+        Lexer {
+            base: offset,
+            errors: Vec::new(),
+            finished: false,
+            in_brackets: false,
+            indents: vec![(0, 0)],
+            iter: multipeek(source.char_indices()),
+            offset: 0,
+            pending: VecDeque::new(),
+            reported: 0,
+            source: source,
+        }
     }
 
-    fn lex_line(&mut self) -> Result<(u8, Vec<Lexeme<'a>>), LexerError<'a>> {
+    fn lex_line(&mut self) -> Result<(Vec<Lexeme<'a>>, Span), LexerError<'a>> {
         let mut line = Vec::new();
-        let mut indent = 0;
         let mut delimit_stack = Vec::new();
 
-        self.reset_peek();
+        let line_start = self.offset;
+        let indent = if self.in_brackets { (0, 0) } else { self.lex_indentation() };
 
-        while let Some(&(_, ch)) = self.peek() {
-            match ch {
-                ' ' | '\t' => {
-                    indent += 1;
-                    self.consume();
-                }
-                NEWLINE | FORMFEED | CARRIAGE => {
-                    self.consume();
-                    return Err(Continue);
-                }
-                _ => break,
+        match self.peek_char() {
+            None => return Err(Eof),
+            Some(_) if self.in_brackets => {}
+            Some('#') => {
+                self.skip_comment();
+                let _ = self.consume_newline();
+                return Err(Continue);
+            }
+            Some(NEWLINE) | Some(CARRIAGE) => {
+                let _ = self.consume_newline();
+                return Err(Continue);
             }
+            Some(_) => {}
         }
 
-        for &last in self.stack.iter().rev() {
-            if indent == last {
-                break;
-            } else if indent > last {
-                line.push(Lexeme::Indent);
-                break;
-            } else {
-                line.push(Lexeme::Dedent);
-            }
+        if let Err(error) = self.push_indentation(indent, line_start, &mut line) {
+            self.error(error);
         }
 
-        'line: loop {
-            let (start, ch) = get_or_eof!(self.next());
+        let indentation = line.len();
+
+        loop {
+            let (start, ch) = match self.next_char() {
+                Some(next) => next,
+                None if line.len() == indentation => return Err(Eof),
+                None => {
+                    for &(span, _) in &delimit_stack {
+                        self.error(UnclosedDelimiter(span));
+                    }
+                    return Ok((line, self.span(self.offset)));
+                }
+            };
 
-            if ch.is_alphabetic() || ch == '_' {
-                line.push(self.lex_word(start)?);
+            let lexeme = if is_word_start(ch) {
+                self.lex_word(start)
             } else if ch.is_digit(10) {
-                line.push(self.lex_number(ch, start)?);
+                self.lex_number(ch, start)
             } else if ch == '.' {
-                line.push(self.lex_leading_dot(start)?);
+                self.lex_leading_dot(start)
             } else if ch == '\'' || ch == '"' {
-                const NO_PREFIX: [Prefix; 2] = [Prefix::Ignore, Prefix::Ignore];
-                line.push(self.lex_str(start, ch, NO_PREFIX)?);
+                self.lex_str(start, ch, NO_PREFIX)
             } else if Operator::is_operator_term(ch) {
-                line.push(self.lex_operator(start)?);
+                self.lex_operator(start)
             } else if let Some(delimiter) = Delimiter::is_delimiter(ch) {
-                line.push(self.lex_delimiter(start, delimiter, &mut delimit_stack));
+                Ok(self.lex_delimiter(start, delimiter, &mut delimit_stack))
             } else if ch == '#' {
-                loop {
-                    let &(_, ch) = get_or_eof!(self.peek());
-
-                    if ch == NEWLINE || ch == CARRIAGE || ch == FORMFEED {
-                        continue 'line;
-                    } else {
-                        self.consume();
-                    }
-                }
+                self.skip_comment();
+                continue;
             } else if ch == ESCAPE {
-                self.lex_escape()?;
-            } else if ch == NEWLINE || ch == CARRIAGE || ch == FORMFEED {
-                if delimit_stack.is_empty() {
-                    break 'line;
+                match self.lex_escape(start) {
+                    Ok(()) => continue,
+                    Err(error) => Err(error),
                 }
+            } else if ch == NEWLINE || ch == CARRIAGE {
+                if ch == CARRIAGE && self.peek_char() == Some(NEWLINE) {
+                    self.consume();
+                }
+                if delimit_stack.is_empty() && !self.in_brackets {
+                    let newline = self.span(start);
+                    return Ok((line, newline));
+                }
+                continue;
+            } else if ch.is_whitespace() {
+                continue;
+            } else {
+                Err(InvalidCharacter(self.span(start), ch))
+            };
+
+            match lexeme {
+                Ok(lexeme) => line.push(lexeme),
+                Err(error) => self.error(error),
             }
         }
-        Ok((indent, line))
     }
 
-    fn consume(&mut self) {
-        let _ = self.next();
+    fn error(&mut self, error: LexerError<'a>) {
+        self.errors.push(error);
     }
 
-    fn lex_leading_dot(&mut self, start: usize) -> LexResult<'a> {
-        let mut string = String::from(".");
+    /// Lexes the next line into `pending`, returning false at the end of the
+    /// source.
+    fn fill_pending(&mut self) -> bool {
+        let line = loop {
+            match self.lex_line() {
+                Err(LexerError::Continue) => continue,
+                Err(LexerError::Eof) => break None,
+                Err(error) => self.error(error),
+                Ok(line) => break Some(line),
+            }
+        };
 
-        loop {
-            if let Some(&(_, ch)) = self.peek() {
-                if !ch.is_digit(10) && ch != 'e' && ch != '+' && ch != '-' {
-                    break;
+        for error in &self.errors[self.reported..] {
+            self.pending.push_back(Err(error.clone()));
+        }
+        self.reported = self.errors.len();
+
+        match line {
+            Some((lexemes, newline)) => {
+                self.pending.extend(lexemes.into_iter().map(Ok));
+                self.pending.push_back(Ok(Lexeme::Newline(newline)));
+                true
+            }
+            None => {
+                // Close every block still open at the end of the file.
+                while self.indents.len() > 1 {
+                    let _ = self.indents.pop();
+                    let end = self.span(self.offset);
+                    self.pending.push_back(Ok(Lexeme::Dedent(end)));
                 }
-                self.consume();
-                string.push(ch);
-            } else {
-                break;
+                !self.pending.is_empty()
             }
         }
+    }
+
+    fn next_char(&mut self) -> Option<(usize, char)> {
+        let next = self.iter.next();
+        if let Some((index, ch)) = next {
+            self.offset = index + ch.len_utf8();
+        }
+        next
+    }
 
-        if string == "." {
-            Ok(Lexeme::Operator(Operator::Access))
-        } else {
-            Ok(self.lex_float(&string, start)?)
+    fn consume(&mut self) {
+        let _ = self.next_char();
+    }
+
+    /// The next character, without consuming it or moving the peek cursor
+    /// past it.
+    fn peek_char(&mut self) -> Option<char> {
+        self.reset_peek();
+        let ch = self.peek().map(|&(_, ch)| ch);
+        self.reset_peek();
+        ch
+    }
+
+    /// The character `n` places after the next one, without consuming
+    /// anything.
+    fn peek_nth_char(&mut self, n: usize) -> Option<char> {
+        self.reset_peek();
+        for _ in 0..n {
+            let _ = self.peek();
+        }
+        let ch = self.peek().map(|&(_, ch)| ch);
+        self.reset_peek();
+        ch
+    }
+
+    /// The span from `start` up to the last consumed character.
+    fn span(&self, start: usize) -> Span {
+        Span::new(self.base + start, self.base + self.offset)
+    }
+
+    fn lex_leading_dot(&mut self, start: usize) -> LexResult<'a> {
+        match self.peek_char() {
+            Some(ch) if ch.is_digit(10) => self.lex_decimal(start, false),
+            _ => self.lex_operator(start),
         }
     }
 
     fn lex_delimiter(&mut self,
                      start: usize,
                      delimiter: Delimiter,
-                     delimit_stack: &mut Vec<Delimiter>)
+                     delimit_stack: &mut Vec<(Span, Delimiter)>)
         -> Lexeme<'a>
     {
+        let span = self.span(start);
         let mut pop = false;
 
         if delimiter.is_opening() {
-            delimit_stack.push(delimiter);
-        } else if let Some(opening) = delimit_stack.last() {
+            delimit_stack.push((span, delimiter));
+        } else if let Some(&(_, opening)) = delimit_stack.last() {
             if opening.is_matching(delimiter) {
                 pop = true;
             }
@@ -172,441 +287,1493 @@ impl<'a> Lexer<'a> {
             let _ = delimit_stack.pop();
         }
 
-        Lexeme::Delimiter(start, delimiter)
+        Lexeme::Delimiter(span, delimiter)
     }
 
-    fn lex_number(&mut self, ch: char, start: usize)
-        -> LexResult<'a>
-    {
-        if ch == '0' {
-            if let Some(&(_, ch)) = self.peek() {
-                if !ch.is_whitespace() && !Operator::is_operator_term(ch) {
-                    self.consume();
-                    let number = match ch {
-                        'b' | 'B' => self.lex_binary(),
-                        'o' | 'O' => self.lex_octal(),
-                        'x' | 'X' => self.lex_hex(),
-                        _ => self.lex_leading_zero(start)?,
-                    };
-                    Ok(number)
-                } else {
-                    Ok(Lexeme::Integer(0))
-                }
-            } else {
-                Ok(Lexeme::Integer(0))
-            }
+    /// Lexes a numeric literal whose first digit, `first`, is at `start`. A
+    /// malformed literal is skipped entirely, so it doesn't leave pieces
+    /// behind to be lexed as names.
+    fn lex_number(&mut self, first: char, start: usize) -> LexResult<'a> {
+        let radix = match (first, self.peek_char()) {
+            ('0', Some('b')) | ('0', Some('B')) => 2,
+            ('0', Some('o')) | ('0', Some('O')) => 8,
+            ('0', Some('x')) | ('0', Some('X')) => 16,
+            _ => 10,
+        };
+
+        let number = if radix == 10 {
+            self.lex_decimal(start, true)
         } else {
-            self.lex_integer(ch, start)
-        }
-    }
-
-    fn lex_integer(&mut self, number: char, start: usize) -> LexResult<'a> {
-        let mut literal = String::new();
-        literal.push(number);
+            self.consume();
+            self.lex_radix(start, radix)
+        };
 
-        loop {
-            if let Some(&(_, ch)) = self.peek() {
-                if !ch.is_digit(10) &&
-                    ch != '_' &&
-                    ch != '.' &&
-                    ch != 'e' &&
-                    ch != '+' &&
-                    ch != '-' {
+        if number.is_err() {
+            while let Some(ch) = self.peek_char() {
+                if !ch.is_alphanumeric() && ch != '_' && ch != '.' {
                     break;
                 }
                 self.consume();
-
-                match ch {
-                    '_' => {}
-                    '0'...'9' | '.' | 'e' | '+' | '-' => literal.push(ch),
-                    _ => return Err(InvalidInteger(start)),
-                }
-            } else {
-                break;
             }
         }
+        number
+    }
 
-        if literal.contains(|c| c == '.' || c == 'e') {
-            Ok(self.lex_float(&literal, start)?)
-        } else {
-            Ok(Lexeme::Integer(literal.parse::<i64>().expect("ICE: Wasn't valid integer.")))
+    /// Lexes the digits of a `0b`, `0o` or `0x` literal.
+    fn lex_radix(&mut self, start: usize, radix: u32) -> LexResult<'a> {
+        let mut digits = String::new();
+
+        let count = self.lex_digit_part(&mut digits, radix, true)?;
+        self.lex_end_of_number(start, radix)?;
+        if count == 0 {
+            return Err(InvalidNumber(self.span(start), radix));
         }
-    }
 
-    fn lex_float(&mut self, literal: &str, start: usize) -> LexResult<'a> {
-        match literal.parse::<f64>() {
-            Ok(float) => Ok(Lexeme::Float(float)),
-            Err(_) => Err(InvalidFloat(start)),
+        match Int::from_str_radix(&digits, radix) {
+            Some(number) => Ok(Lexeme::Integer(self.span(start), number)),
+            None => Err(InvalidNumber(self.span(start), radix)),
         }
     }
 
-    fn lex_leading_zero(&mut self, start: usize) -> LexResult<'a> {
+    /// Lexes a decimal integer, float or imaginary literal. `integer` is
+    /// false when the literal starts with its fraction, like `.5`.
+    fn lex_decimal(&mut self, start: usize, integer: bool) -> LexResult<'a> {
         let mut literal = String::new();
-        loop {
+        let mut float = !integer;
 
-            if let Some(&(_, ch)) = self.peek() {
+        if integer {
+            literal.push_str(&self.source[start..self.offset]);
+            self.lex_digit_part(&mut literal, 10, true)?;
 
-                match ch {
-                    '_' => {},
-                    '0'...'9' | '.' | 'e' | '+' | '-' => literal.push(ch),
-                    _ => break,
-                }
+            if self.peek_char() == Some('.') {
                 self.consume();
-            } else {
-                break;
+                literal.push('.');
+                float = true;
+                self.lex_digit_part(&mut literal, 10, false)?;
             }
-        }
-        if literal.is_empty() || literal.chars().all(|c| c == '0') {
-            Ok(Lexeme::Integer(0))
         } else {
-            Ok(self.lex_float(&literal, start)?)
+            literal.push('.');
+            self.lex_digit_part(&mut literal, 10, false)?;
         }
-    }
 
-    fn lex_binary(&mut self) -> Lexeme<'a> {
-        let mut number = 0;
-        loop {
-            if let Some(&(_, ch)) = self.peek() {
-                if ch.is_digit(2) && ch == '_' {
-                    break;
-                }
+        let exponent = self.peek_char().map(|ch| ch.to_ascii_lowercase()) == Some('e');
+        if exponent && !self.at_number_keyword() {
+            let digit = match self.peek_nth_char(1) {
+                Some('+') | Some('-') => self.peek_nth_char(2),
+                ch => ch,
+            };
 
+            if !digit.map_or(false, |ch| ch.is_digit(10)) {
                 self.consume();
-                number <<= 1;
-
-                match ch {
-                    '0' => {},
-                    '1' => number |= 1,
-                    '_' => number >>= 1,
-                    _ => break,
+                if let Some('+') | Some('-') = self.peek_char() {
+                    self.consume();
                 }
-            } else {
-                break;
+                return Err(InvalidNumber(self.span(start), 10));
             }
-        }
-        Lexeme::Integer(number)
-    }
 
-    fn lex_octal(&mut self) -> Lexeme<'a> {
-        let mut number = 0;
-        loop {
-            if let Some(&(_, ch)) = self.peek() {
+            self.consume();
+            literal.push('e');
+            if let Some(sign) = self.peek_char().filter(|&ch| ch == '+' || ch == '-') {
+                self.consume();
+                literal.push(sign);
+            }
+            self.lex_digit_part(&mut literal, 10, false)?;
+            float = true;
+        }
 
-                if !ch.is_digit(8) || ch != '_' {
-                    break;
-                }
-                number <<= 3;
+        let imaginary = match self.peek_char() {
+            Some('j') | Some('J') => {
                 self.consume();
+                true
+            }
+            _ => false,
+        };
 
-                match ch {
-                    '_' => number >>= 3,
-                    '0' => {}
-                    '1' => number |= 1,
-                    '2' => number |= 2,
-                    '3' => number |= 3,
-                    '4' => number |= 4,
-                    '5' => number |= 5,
-                    '6' => number |= 6,
-                    '7' => number |= 7,
-                    _ => break,
-                }
+        self.lex_end_of_number(start, 10)?;
+
+        if imaginary || float {
+            // Integers too big for a float become infinite, as in Python.
+            let value = match literal.parse::<f64>() {
+                Ok(value) => value,
+                Err(_) => return Err(InvalidNumber(self.span(start), 10)),
+            };
+
+            return if imaginary {
+                Ok(Lexeme::Imaginary(self.span(start), value))
             } else {
-                break;
-            }
+                Ok(Lexeme::Float(self.span(start), value))
+            };
         }
-        Lexeme::Integer(number)
-    }
 
-    fn lex_hex(&mut self) -> Lexeme<'a> {
-        let mut number = 0;
-        loop {
+        if literal.starts_with('0') && literal.bytes().any(|byte| byte != b'0') {
+            return Err(LeadingZeros(self.span(start)));
+        }
 
-            if let Some(&(_, ch)) = self.peek() {
-                let byte = ch as u8;
+        match Int::from_str_radix(&literal, 10) {
+            Some(number) => Ok(Lexeme::Integer(self.span(start), number)),
+            None => Err(InvalidNumber(self.span(start), 10)),
+        }
+    }
 
-                if !ch.is_digit(16) && ch != '_' {
-                    break;
-                }
+    /// Reads digits in `radix` onto `literal`, returning how many there were.
+    /// Single underscores may separate digits, and may come before the first
+    /// one when `leading_underscore` is set, as after a `0x` prefix or when
+    /// the caller has already read a digit.
+    fn lex_digit_part(&mut self,
+                      literal: &mut String,
+                      radix: u32,
+                      leading_underscore: bool)
+        -> Result<usize, LexerError<'a>>
+    {
+        let mut count = 0;
+
+        loop {
+            let ch = match self.peek_char() {
+                Some(ch) => ch,
+                None => break,
+            };
 
-                number <<= 4;
+            if ch == '_' && (count > 0 || leading_underscore) {
+                let underscore = self.offset;
                 self.consume();
 
-                match byte {
-                    b'A'...b'F' => number |= (byte - b'A' + 10) as i64,
-                    b'a'...b'f' => number |= (byte - b'a' + 10) as i64,
-                    b'0'...b'9' => number |= (byte - b'0') as i64,
-                    b'_' => number >>= 4,
-                    _ => break,
+                match self.peek_char() {
+                    Some(ch) if ch.is_digit(radix) => {}
+                    Some(ch) if ch.is_digit(10) => {
+                        let digit = self.offset;
+                        self.consume();
+                        return Err(InvalidDigit(self.span(digit), ch, radix));
+                    }
+                    _ => return Err(InvalidUnderscore(self.span(underscore))),
                 }
+            } else if ch.is_digit(radix) {
+                self.consume();
+                literal.push(ch);
+                count += 1;
+            } else if ch.is_digit(10) {
+                let digit = self.offset;
+                self.consume();
+                return Err(InvalidDigit(self.span(digit), ch, radix));
             } else {
                 break;
             }
         }
-        Lexeme::Integer(number)
+
+        Ok(count)
     }
 
-    fn lex_escape(&mut self) -> Result<(), LexerError<'a>> {
-        match self.peek() {
-            Some(&(_, NEWLINE)) | Some(&(_, CARRIAGE)) | Some(&(_, FORMFEED)) =>
-            {
+    /// A number can't run straight into a name, `0x1g` or `1a` are errors.
+    /// Like CPython, a keyword that can follow an expression is still
+    /// allowed, as in `1if x else 2`.
+    fn lex_end_of_number(&mut self, start: usize, radix: u32)
+        -> Result<(), LexerError<'a>>
+    {
+        match self.peek_char() {
+            Some(_) if self.at_number_keyword() => Ok(()),
+            Some(ch) if ch.is_alphanumeric() || ch == '_' => {
                 self.consume();
-                Ok(())
+                Err(InvalidNumber(self.span(start), radix))
             }
-            Some(&(start, _)) => Err(InvalidEscape(start)),
-            _ => Err(Eof),
+            _ => Ok(()),
         }
     }
 
-    fn lex_operator(&mut self, start: usize) -> LexResult<'a> {
-        let mut end = start;
-
-        if let Some(&(y_end, y)) = self.peek() {
-            if Operator::is_operator_term(y) {
-                if let Some(&(z_end, z)) = self.peek() {
-                    if Operator::is_operator_term(z) {
-                        end = z_end;
-                    } else {
-                        end = y_end;
-                    }
-                } else {
-                    end = y_end;
-                }
-            }
+    /// Whether the word at the offset is one of the keywords CPython allows
+    /// straight after a number. CPython only checks the keyword's letters,
+    /// so it takes `1orange` as `1 or ange`, but here the whole word has to
+    /// match.
+    fn at_number_keyword(&self) -> bool {
+        let rest = &self.source[self.offset..];
+        let end = rest.find(|ch| !is_word_continue(ch)).unwrap_or(rest.len());
+        match &rest[..end] {
+            "and" | "else" | "for" | "if" | "in" | "is" | "not" | "or" => true,
+            _ => false,
         }
+    }
 
-        let word = &self.source[start..end+1];
-
-        if let Some(operator) = Operator::is_operator(word) {
-            let operator = match operator {
-                operator @ Operator::Add | operator @ Operator::Sub => {
-                    if let Some(&(_, ref ch)) = self.peek() {
-                        if ch.is_alphanumeric() {
-                            match operator {
-                                Operator::Add => Operator::UnaryAdd,
-                                Operator::Sub => Operator::UnarySub,
-                                Operator::Not => Operator::UnaryNot,
-                                _ => unreachable!(),
-                            }
-                        } else {
-                            operator
-                        }
-                    } else {
-                        operator
-                    }
-                }
-                operator => operator,
-            };
-
-            Ok(Lexeme::Operator(operator))
+    fn lex_escape(&mut self, start: usize) -> Result<(), LexerError<'a>> {
+        if self.consume_newline() {
+            Ok(())
         } else {
-            Err(InvalidOperator(start, word))
+            Err(InvalidEscape(self.span(start)))
         }
     }
 
-    fn lex_str(&mut self, start: usize, quote: char, prefixes: [Prefix; 2])
-        -> LexResult<'a>
-    {
-        let mut quote_len = 1;
-        let mut string = String::new();
-
-        if let Some(&(_, ch)) = self.peek() {
-            if ch == quote {
-                if let Some(&(_, ch)) = self.peek() {
-                    if ch == quote {
-                        self.consume();
-                        self.consume();
-                        quote_len = 3;
-                    }
-                }
-            }
-        } else {
-            return Err(Eof)
+    /// Reads the whitespace at the start of a line, returning its columns
+    /// as given by `indentation`.
+    fn lex_indentation(&mut self) -> (usize, usize) {
+        let start = self.offset;
+        while let Some(' ') | Some('\t') | Some(FORMFEED) = self.peek_char() {
+            self.consume();
         }
+        indentation(&self.source[start..self.offset])
+    }
 
-        loop {
-            let (_, ch) = get_or_eof!(self.next());
-
-            if ch == quote {
-                if quote_len == 3 {
-                    if let Some(&(_, ch)) = self.peek() {
-                        if ch == quote {
-                            if let Some(&(_, ch)) = self.peek() {
-                                if ch == quote {
-                                    self.consume();
-                                    self.consume();
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    break;
-                }
-            } else if ch == ESCAPE && !prefixes.contains(&Prefix::Raw) {
-                let raw = match get_or_eof!(self.next()) {
-                    (_, 't') => '\t',
-                    (_, 'n') => '\n',
-                    (_, 'r') => '\r',
-                    (_, '\'') => '\'',
-                    (_, '"') => '\"',
-                    (_, 'a') => '\u{7}',
-                    (_, 'b') => '\u{8}',
-                    (_, 'f') => '\u{21A1}',
-                    (_, 'v') => '\u{11}',
-                    (_, '\\') => '\\',
-                    (_, '\n') =>  continue,
-                    (_, 'x') => {
-                        let number = match self.lex_hex() {
-                            Lexeme::Integer(number) => number,
-                            _ => unreachable!(),
-                        };
-
-                        if number > u8::max_value() as i64 {
-                            return Err(InvalidHex(start))
-                        }
+    /// Compares the indentation of a line against the enclosing blocks,
+    /// pushing an `Indent` or as many `Dedent`s as it closes.
+    fn push_indentation(&mut self,
+                        indent: (usize, usize),
+                        line_start: usize,
+                        line: &mut Vec<Lexeme<'a>>)
+        -> Result<(), LexerError<'a>>
+    {
+        let (column, alternate) = indent;
+        let span = self.span(line_start);
+        let mut last = *self.indents.last().expect("the outermost level is never popped");
+
+        if column > last.0 {
+            self.indents.push(indent);
+            line.push(Lexeme::Indent(span));
+            return if alternate <= last.1 {
+                Err(InconsistentIndentation(span))
+            } else {
+                Ok(())
+            };
+        }
 
-                        match char::from_u32(number as u32) {
-                            Some(c) => c,
-                            None => return Err(InvalidHex(start))
-                        }
-                    },
-                    (_, 'u') if !prefixes.contains(&Prefix::Bytes) => {
-                        let number = match self.lex_hex() {
-                            Lexeme::Integer(number) => number,
-                            _ => unreachable!(),
-                        };
-
-                        if number > u16::max_value() as i64 {
-                            return Err(InvalidUnicode16(start))
-                        }
+        while column < last.0 {
+            let _ = self.indents.pop();
+            let outer = *self.indents.last().expect("the outermost level is never popped");
 
-                        match char::from_u32(number as u32) {
-                            Some(c) => c,
-                            None => return Err(InvalidUnicode16(start)),
-                        }
-                    },
-                    (_, 'U') if !prefixes.contains(&Prefix::Bytes) => {
-                        let number = match self.lex_hex() {
-                            Lexeme::Integer(number) => number,
-                            _ => unreachable!(),
-                        };
-
-                        if number > u32::max_value() as i64 {
-                            return Err(InvalidUnicode32(start))
-                        }
+            if column > outer.0 {
+                // Keep the block open at this column instead, so the lines
+                // after it that line up with it don't report the same error.
+                self.indents.push(indent);
+                return Err(UnmatchedDedent(span));
+            }
 
-                        match char::from_u32(number as u32) {
-                            Some(c) => c,
-                            None => return Err(InvalidUnicode32(start)),
-                        }
-                    },
-                    (x, _) => {
-                        let _ = get_or_eof!(self.next());
-                        let (z, _) = get_or_eof!(self.next());
-                        let mut converted: u32 = 0;
-
-                        let mut octal = match self.source[x..z+1].parse::<u32>() {
-                            Ok(number) => number,
-                            _ => return Err(InvalidOctal(x)),
-                        };
-
-                        let mut i = 0;
-                        while octal > 0 {
-                            i += 1;
-                            converted +=  octal % 10 * u32::pow(8, i);
-                            octal /= 10;
-                        }
+            line.push(Lexeme::Dedent(Span::empty(span.end)));
+            last = outer;
+        }
 
-                        match char::from_u32(converted) {
-                            Some(c) => c,
-                            None => return Err(InvalidOctal(x)),
-                        }
-                    }
-                };
+        if alternate != last.1 {
+            Err(InconsistentIndentation(span))
+        } else {
+            Ok(())
+        }
+    }
 
-                string.push(raw);
-            } else {
-                string.push(ch);
+    /// Skips to the end of a comment, leaving the newline.
+    fn skip_comment(&mut self) {
+        while let Some(ch) = self.peek_char() {
+            if ch == NEWLINE || ch == CARRIAGE {
+                break;
             }
+            self.consume();
         }
+    }
 
-        if prefixes.contains(&Prefix::Bytes) {
-            Ok(Lexeme::Bytes(start, string.into_bytes()))
-        } else {
-            Ok(Lexeme::Str(start, string))
+    /// Consumes a `\n`, `\r` or `\r\n`, returning whether there was one.
+    fn consume_newline(&mut self) -> bool {
+        match self.peek_char() {
+            Some(NEWLINE) => self.consume(),
+            Some(CARRIAGE) => {
+                self.consume();
+                if self.peek_char() == Some(NEWLINE) {
+                    self.consume();
+                }
+            }
+            _ => return false,
         }
+        true
     }
 
-    fn lex_word(&mut self, start: usize) -> LexResult<'a> {
-        let mut end = start;
-        loop {
-            let &(new_end, ch) = get_or_eof!(self.peek());
-            if !ch.is_alphanumeric() && ch != '_' {
-                break;
+    /// Lexes the longest operator starting at `start`, so `**=` is one
+    /// operator rather than `**` and `=`, or `*` and `*=`.
+    fn lex_operator(&mut self, start: usize) -> LexResult<'a> {
+        let longest = (1..MAX_OPERATOR_LEN + 1).rev().filter_map(|length| {
+            self.source.get(start..start + length)
+                       .and_then(Operator::is_operator)
+                       .map(|operator| (length, operator))
+        }).next();
+
+        let (length, operator) = match longest {
+            Some(longest) => longest,
+            None => {
+                let word = &self.source[start..self.offset];
+                return Err(InvalidOperator(self.span(start), word));
             }
+        };
+
+        for _ in 1..length {
             self.consume();
-            end = new_end;
         }
 
+        Ok(Lexeme::Operator(self.span(start), operator))
+    }
+
+    fn lex_str(&mut self, start: usize, quote: char, prefixes: [Prefix; 2])
+        -> LexResult<'a>
+    {
+        let raw = prefixes.contains(&Prefix::Raw);
+        let mut string = String::new();
+
+        self.reset_peek();
+        let triple = self.peek().map(|&(_, ch)| ch) == Some(quote) &&
+                     self.peek().map(|&(_, ch)| ch) == Some(quote);
         self.reset_peek();
 
-        let word = &self.source[start..end+1];
+        if triple {
+            self.consume();
+            self.consume();
+        }
 
+        let quotes = Quotes { quote: quote, triple: triple, raw: raw };
 
-        if let Some(&(_, ch)) = self.peek() {
-            if ch == '"' || ch == '\'' {
-                self.consume();
-                match Prefix::is_prefix(word) {
-                    None => return Err(InvalidPrefix(word)),
-                    Some(vec) => return Ok(self.lex_str(start, ch, vec)?),
-                }
-            }
+        if prefixes.contains(&Prefix::Formatted) {
+            let parts = self.lex_format_parts(start, quotes, false);
+            return Ok(Lexeme::FormattedStr(self.span(start), parts));
+        } else if prefixes.contains(&Prefix::Bytes) {
+            let bytes = self.lex_bytes(start, quotes);
+            return Ok(Lexeme::Bytes(self.span(start), bytes));
         }
 
-        if let Some(keyword) = Keyword::is_keyword(word) {
-            Ok(Lexeme::Keyword(start, keyword))
-        } else {
-            Ok(Lexeme::Identifier(start, word))
+        while self.lex_str_body(start, quotes) {
+            self.lex_str_char(raw, &mut string);
         }
+
+        Ok(Lexeme::Str(self.span(start), string))
     }
-}
 
-impl<'a> fmt::Debug for Lexer<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for lexeme in &self.output {
-            match *lexeme {
-                ref n @ Lexeme::Newline => write!(f, " {:?}\n", n)?,
-                ref lexeme => write!(f, " {:?} ", lexeme)?,
+    /// Checks for the end of a string's body, consuming the closing quotes.
+    /// Returns true while there's more of the string to lex.
+    fn lex_str_body(&mut self, start: usize, quotes: Quotes) -> bool {
+        if self.is_closing_quote(quotes.quote, quotes.triple) {
+            self.consume_quotes(quotes.triple);
+            return false;
+        }
+
+        match self.peek_char() {
+            Some(ch) if quotes.triple || (ch != NEWLINE && ch != CARRIAGE) => true,
+            // Leave the newline for `lex_line` so the next line still
+            // lexes normally.
+            _ => {
+                self.error(UnterminatedString(self.span(start)));
+                false
             }
         }
-        write!(f, "")
     }
-}
 
-impl<'a> ops::Deref for Lexer<'a> {
-    type Target = MultiPeek<CharIndices<'a>>;
-    fn deref(&self) -> &Self::Target {
-        &self.iter
-    }
-}
+    /// Lexes the body of a bytes literal. Only ASCII may be written in one,
+    /// anything else has to be escaped, and escapes give single bytes.
+    fn lex_bytes(&mut self, start: usize, quotes: Quotes) -> Vec<u8> {
+        let mut bytes = Vec::new();
 
-impl<'a> ops::DerefMut for Lexer<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.iter
-    }
-}
+        while self.lex_str_body(start, quotes) {
+            let at = self.offset;
+            let ch = match self.next_char() {
+                Some((_, ch)) => ch,
+                None => break,
+            };
 
-#[derive(Clone, Debug)]
+            if !ch.is_ascii() {
+                self.error(NonAsciiBytes(self.span(at)));
+            } else if ch == ESCAPE && quotes.raw {
+                bytes.push(ch as u8);
+                match self.peek_char() {
+                    Some(ch) if ch.is_ascii() => {
+                        self.consume();
+                        bytes.push(ch as u8);
+                    }
+                    _ => {}
+                }
+            } else if ch == ESCAPE {
+                match self.lex_str_escape(at, true) {
+                    // Octal escapes above `\377` wrap, as they do in CPython.
+                    Ok(Some(ch)) => bytes.push(ch as u32 as u8),
+                    Ok(None) => {}
+                    Err(error) => self.error(error),
+                }
+            } else {
+                bytes.push(ch as u8);
+            }
+        }
+
+        bytes
+    }
+
+    fn is_closing_quote(&mut self, quote: char, triple: bool) -> bool {
+        let count = if triple { 3 } else { 1 };
+        self.reset_peek();
+        let closing = (0..count).all(|_| self.peek().map(|&(_, ch)| ch) == Some(quote));
+        self.reset_peek();
+        closing
+    }
+
+    fn consume_quotes(&mut self, triple: bool) {
+        for _ in 0..if triple { 3 } else { 1 } {
+            self.consume();
+        }
+    }
+
+    /// Lexes the next character of a string, handling escapes.
+    fn lex_str_char(&mut self, raw: bool, string: &mut String) {
+        let start = self.offset;
+        let ch = match self.next_char() {
+            Some((_, ch)) => ch,
+            None => return,
+        };
+
+        if ch == ESCAPE && raw {
+            // A raw string still can't end on an escaped quote, but both
+            // characters are kept as written. Braces are left for f-strings
+            // to treat as fields.
+            string.push(ch);
+            match self.peek_char() {
+                Some('{') | Some('}') | None => {}
+                Some(ch) => {
+                    self.consume();
+                    string.push(ch);
+                }
+            }
+        } else if ch == ESCAPE {
+            match self.lex_str_escape(start, false) {
+                Ok(Some(ch)) => string.push(ch),
+                Ok(None) => {}
+                Err(error) => {
+                    self.error(error);
+                    string.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+        } else {
+            string.push(ch);
+        }
+    }
+
+    /// Splits the body of an f-string into literal text and replacement
+    /// fields. With `spec` set this is lexing a format spec, which ends at
+    /// the `}` closing its field.
+    fn lex_format_parts(&mut self, start: usize, quotes: Quotes, spec: bool)
+        -> Vec<FormatPart<'a>>
+    {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+
+        loop {
+            if self.is_closing_quote(quotes.quote, quotes.triple) {
+                // A spec can't close the string, leave it for the field to
+                // report.
+                if !spec {
+                    self.consume_quotes(quotes.triple);
+                }
+                break;
+            }
+
+            let ch = match self.peek_char() {
+                Some('}') if spec => break,
+                Some(ch) if quotes.triple || (ch != NEWLINE && ch != CARRIAGE) => ch,
+                _ => {
+                    if !spec {
+                        self.error(UnterminatedString(self.span(start)));
+                    }
+                    break;
+                }
+            };
+            let brace = self.offset;
+
+            match ch {
+                '{' => {
+                    self.consume();
+                    if !spec && self.peek_char() == Some('{') {
+                        self.consume();
+                        literal.push('{');
+                        continue;
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(FormatPart::Literal(mem::replace(&mut literal, String::new())));
+                    }
+
+                    self.lex_format_field(brace, quotes, &mut parts);
+                }
+                '}' => {
+                    self.consume();
+                    if self.peek_char() == Some('}') {
+                        self.consume();
+                        literal.push('}');
+                    } else {
+                        self.error(UnmatchedFormatBrace(self.span(brace)));
+                    }
+                }
+                _ => self.lex_str_char(quotes.raw, &mut literal),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(FormatPart::Literal(literal));
+        }
+        parts
+    }
+
+    /// Lexes a replacement field whose `{` is at `start` onto `parts`. The
+    /// expression is only delimited here, it's up to the parser to make sense
+    /// of it. A self documenting `{a=}` also pushes its text as a literal.
+    fn lex_format_field(&mut self, start: usize, quotes: Quotes, parts: &mut Vec<FormatPart<'a>>) {
+        let expression_start = self.offset;
+        let mut depth = 0usize;
+        let mut debug = false;
+
+        while let Some(ch) = self.peek_char() {
+            if !quotes.triple && (ch == NEWLINE || ch == CARRIAGE) {
+                break;
+            } else if ch == quotes.quote && self.is_closing_quote(ch, quotes.triple) {
+                break;
+            } else if depth == 0 && (ch == '}' || ch == ':') {
+                break;
+            } else if depth == 0 && ch == '!' {
+                self.reset_peek();
+                let _ = self.peek();
+                let equals = self.peek().map(|&(_, ch)| ch) == Some('=');
+                self.reset_peek();
+
+                if !equals {
+                    break;
+                }
+                self.consume();
+            } else if depth == 0 && ch == '=' {
+                self.reset_peek();
+                let _ = self.peek();
+                let equals = self.peek().map(|&(_, ch)| ch) == Some('=');
+                self.reset_peek();
+
+                // Only a lone `=` ends the expression, not `==`, `!=`, `<=`
+                // or `>=`.
+                let previous = self.source[expression_start..self.offset].chars().next_back();
+                if equals {
+                    self.consume();
+                } else if previous.map_or(true, |ch| !"=!<>".contains(ch)) {
+                    debug = true;
+                    break;
+                }
+            } else if ch == '\'' || ch == '"' {
+                self.skip_nested_str(ch);
+                continue;
+            }
+
+            match ch {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.consume();
+        }
+
+        let expression = self.span(expression_start);
+        let source = &self.source[expression_start..self.offset];
+
+        let mut text = None;
+        if debug {
+            self.consume();
+            while let Some(' ') | Some('\t') | Some(FORMFEED) = self.peek_char() {
+                self.consume();
+            }
+            text = Some(&self.source[expression_start..self.offset]);
+        }
+
+        let mut conversion = None;
+        if self.peek_char() == Some('!') {
+            let bang = self.offset;
+            self.consume();
+            conversion = match self.peek_char() {
+                Some('a') => Some(Conversion::Ascii),
+                Some('r') => Some(Conversion::Repr),
+                Some('s') => Some(Conversion::Str),
+                _ => None,
+            };
+
+            if conversion.is_some() {
+                self.consume();
+            } else {
+                // Skip the rest of the conversion so it's the only error.
+                while let Some(ch) = self.peek_char() {
+                    if ch == ':' || ch == '}' || ch == NEWLINE || ch == CARRIAGE ||
+                       (ch == quotes.quote && self.is_closing_quote(ch, quotes.triple)) {
+                        break;
+                    }
+                    self.consume();
+                }
+                self.error(InvalidConversion(self.span(bang)));
+            }
+        }
+
+        let mut format_spec = Vec::new();
+        let has_spec = self.peek_char() == Some(':');
+        if has_spec {
+            self.consume();
+            format_spec = self.lex_format_parts(start, quotes, true);
+        }
+
+        if self.peek_char() == Some('}') {
+            self.consume();
+        } else {
+            self.error(UnclosedFormatField(self.span(start)));
+            return;
+        }
+
+        if source.trim().is_empty() {
+            self.error(EmptyFormatExpression(self.span(start)));
+            return;
+        }
+
+        if let Some(text) = text {
+            // Without a conversion or spec the value is shown as its repr.
+            if conversion.is_none() && !has_spec {
+                conversion = Some(Conversion::Repr);
+            }
+            parts.push(FormatPart::Literal(text.to_owned()));
+        }
+
+        parts.push(FormatPart::Field(FormatField {
+            expression: expression,
+            source: source,
+            conversion: conversion,
+            format_spec: format_spec,
+        }));
+    }
+
+    /// Skips over a string inside an f-string expression, so that braces
+    /// and colons inside it aren't taken as part of the field.
+    fn skip_nested_str(&mut self, quote: char) {
+        self.consume();
+
+        self.reset_peek();
+        let triple = self.peek().map(|&(_, ch)| ch) == Some(quote) &&
+                     self.peek().map(|&(_, ch)| ch) == Some(quote);
+        self.reset_peek();
+
+        if triple {
+            self.consume_quotes(false);
+            self.consume_quotes(false);
+        }
+
+        loop {
+            if self.is_closing_quote(quote, triple) {
+                self.consume_quotes(triple);
+                break;
+            }
+
+            match self.peek_char() {
+                Some(ESCAPE) => {
+                    self.consume();
+                    self.consume();
+                }
+                Some(ch) if triple || (ch != NEWLINE && ch != CARRIAGE) => {
+                    self.consume();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Lexes the escape sequence following a backslash at `start`. Returns
+    /// `None` for an escaped newline, which adds nothing to the string.
+    fn lex_str_escape(&mut self, start: usize, bytes: bool)
+        -> Result<Option<char>, LexerError<'a>>
+    {
+        let ch = match self.peek_char() {
+            Some(ch) => ch,
+            None => return Ok(Some(ESCAPE)),
+        };
+
+        let escaped = match ch {
+            '\\' => '\\',
+            '\'' => '\'',
+            '"' => '"',
+            'a' => '\u{7}',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\u{b}',
+            '\n' => {
+                self.consume();
+                return Ok(None);
+            }
+            '\r' => {
+                self.consume();
+                if self.peek_char() == Some('\n') {
+                    self.consume();
+                }
+                return Ok(None);
+            }
+            '0'...'7' => {
+                let mut number = 0;
+                for _ in 0..3 {
+                    match self.peek_char().and_then(|ch| ch.to_digit(8)) {
+                        Some(digit) => {
+                            self.consume();
+                            number = number * 8 + digit;
+                        }
+                        None => break,
+                    }
+                }
+
+                return match char::from_u32(number) {
+                    Some(ch) => Ok(Some(ch)),
+                    None => Err(InvalidEscape(self.span(start))),
+                };
+            }
+            'x' => {
+                self.consume();
+                return match self.lex_escape_digits(2) {
+                    Some(number) => Ok(char::from_u32(number)),
+                    None => Err(InvalidHex(self.span(start))),
+                };
+            }
+            'u' if !bytes => {
+                self.consume();
+                return match self.lex_escape_digits(4).and_then(char::from_u32) {
+                    Some(ch) => Ok(Some(ch)),
+                    None => Err(InvalidUnicode16(self.span(start))),
+                };
+            }
+            'N' if !bytes => {
+                self.consume();
+                let name = match self.lex_escape_name() {
+                    Some(name) => name,
+                    None => return Err(InvalidEscape(self.span(start))),
+                };
+
+                return match unicode_names::lookup(name) {
+                    Some(ch) => Ok(Some(ch)),
+                    None => Err(UnknownUnicodeName(self.span(start), name)),
+                };
+            }
+            'U' if !bytes => {
+                self.consume();
+                return match self.lex_escape_digits(8).and_then(char::from_u32) {
+                    Some(ch) => Ok(Some(ch)),
+                    None => Err(InvalidUnicode32(self.span(start))),
+                };
+            }
+            // Unrecognised escapes are left in the string untouched.
+            _ => return Ok(Some(ESCAPE)),
+        };
+
+        self.consume();
+        Ok(Some(escaped))
+    }
+
+    /// Reads the `{NAME}` part of a `\N{NAME}` escape.
+    fn lex_escape_name(&mut self) -> Option<&'a str> {
+        if self.peek_char() != Some('{') {
+            return None;
+        }
+        self.consume();
+
+        let start = self.offset;
+        while let Some(ch) = self.peek_char() {
+            if !ch.is_ascii_alphanumeric() && ch != ' ' && ch != '-' {
+                break;
+            }
+            self.consume();
+        }
+        let end = self.offset;
+
+        if self.peek_char() == Some('}') && start != end {
+            self.consume();
+            Some(&self.source[start..end])
+        } else {
+            None
+        }
+    }
+
+    /// Reads exactly `count` hex digits, as used by `\x`, `\u` and `\U`.
+    fn lex_escape_digits(&mut self, count: usize) -> Option<u32> {
+        let mut number: u32 = 0;
+        for _ in 0..count {
+            let digit = self.peek_char().and_then(|ch| ch.to_digit(16))?;
+            self.consume();
+            number = number.checked_mul(16)? + digit;
+        }
+        Some(number)
+    }
+
+    fn lex_word(&mut self, start: usize) -> LexResult<'a> {
+        while let Some(ch) = self.peek_char() {
+            if !is_word_continue(ch) {
+                break;
+            }
+            self.consume();
+        }
+
+        let word = &self.source[start..self.offset];
+
+        if let Some(ch) = self.peek_char() {
+            if ch == '"' || ch == '\'' {
+                self.consume();
+                return match Prefix::is_prefix(word) {
+                    Some(prefixes) => self.lex_str(start, ch, prefixes),
+                    None => {
+                        // Still lex the string, so its contents aren't
+                        // mistaken for code.
+                        self.error(InvalidPrefix(self.span(start), word));
+                        self.lex_str(start, ch, NO_PREFIX)
+                    }
+                };
+            }
+        }
+
+        if let Some(keyword) = Keyword::is_keyword(word) {
+            return Ok(Lexeme::Keyword(self.span(start), keyword));
+        }
+
+        if word.is_ascii() {
+            return Ok(Lexeme::Identifier(self.span(start), Cow::Borrowed(word)));
+        }
+
+        // PEP 3131, names are compared after NFKC normalization, and it's the
+        // normalized name that has to be made of XID characters.
+        let name: String = word.nfkc().collect();
+        if is_identifier(&name) {
+            return Ok(Lexeme::Identifier(self.span(start), Cow::Owned(name)));
+        }
+
+        let mut offset = start;
+        for ch in word.chars() {
+            let normalized: String = ch.nfkc().collect();
+            let valid = if offset == start {
+                is_identifier(&normalized)
+            } else {
+                normalized.chars().all(|ch| ch.is_xid_continue())
+            };
+
+            if !valid {
+                let span = Span::new(self.base + offset, self.base + offset + ch.len_utf8());
+                return Err(InvalidIdentifier(span, ch));
+            }
+            offset += ch.len_utf8();
+        }
+        Err(InvalidIdentifier(self.span(start), word.chars().next().unwrap()))
+    }
+}
+
+/// The column of the whitespace `text` with tabs to the next multiple of
+/// `TAB_SIZE`, and its column with tabs counted as one. Python compares both,
+/// so that indentation can't depend on how wide a tab is. A form feed resets
+/// both to zero.
+pub fn indentation(text: &str) -> (usize, usize) {
+    let mut column = 0;
+    let mut alternate = 0;
+
+    for ch in text.chars() {
+        match ch {
+            '\t' => {
+                column = (column / TAB_SIZE + 1) * TAB_SIZE;
+                alternate += 1;
+            }
+            FORMFEED => {
+                column = 0;
+                alternate = 0;
+            }
+            _ => {
+                column += 1;
+                alternate += 1;
+            }
+        }
+    }
+
+    (column, alternate)
+}
+
+/// Whether `ch` may start a name. Like CPython, any non-ASCII character
+/// that isn't whitespace starts one, so that `lex_word` can say exactly what
+/// is wrong with it.
+fn is_word_start(ch: char) -> bool {
+    ch == '_' || ch.is_ascii_alphabetic() || (!ch.is_ascii() && !ch.is_whitespace())
+}
+
+fn is_word_continue(ch: char) -> bool {
+    is_word_start(ch) || ch.is_ascii_digit()
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first == '_' || first.is_xid_start() => {
+            chars.all(|ch| ch.is_xid_continue())
+        }
+        _ => false,
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = LexResult<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.finished {
+            self.finished = !self.fill_pending();
+        }
+        self.pending.pop_front()
+    }
+}
+
+impl<'a> fmt::Debug for Lexer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lexer")
+         .field("offset", &(self.base + self.offset))
+         .field("pending", &self.pending)
+         .field("errors", &self.errors)
+         .finish()
+    }
+}
+
+impl<'a> ops::Deref for Lexer<'a> {
+    type Target = MultiPeek<CharIndices<'a>>;
+    fn deref(&self) -> &Self::Target {
+        &self.iter
+    }
+}
+
+impl<'a> ops::DerefMut for Lexer<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.iter
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum LexerError<'a> {
     Continue,
     Eof,
-    InvalidEscape(usize),
-    InvalidHex(usize),
-    InvalidFloat(usize),
-    InvalidInteger(usize),
-    InvalidOctal(usize),
-    InvalidOperator(usize, &'a str),
-    InvalidPrefix(&'a str),
-    InvalidUnicode16(usize),
-    InvalidUnicode32(usize),
+    EmptyFormatExpression(Span),
+    InconsistentIndentation(Span),
+    InvalidCharacter(Span, char),
+    InvalidConversion(Span),
+    InvalidEscape(Span),
+    InvalidHex(Span),
+    InvalidIdentifier(Span, char),
+    InvalidDigit(Span, char, u32),
+    InvalidNumber(Span, u32),
+    InvalidUnderscore(Span),
+    InvalidOperator(Span, &'a str),
+    InvalidPrefix(Span, &'a str),
+    InvalidUnicode16(Span),
+    InvalidUnicode32(Span),
+    LeadingZeros(Span),
+    NonAsciiBytes(Span),
+    UnclosedDelimiter(Span),
+    UnclosedFormatField(Span),
+    UnknownUnicodeName(Span, &'a str),
+    UnmatchedDedent(Span),
+    UnmatchedFormatBrace(Span),
+    UnterminatedString(Span),
+}
+
+impl<'a> LexerError<'a> {
+    /// Where in the source the error is, `None` for the errors only used
+    /// internally to drive the lexer.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Continue | Eof => None,
+            EmptyFormatExpression(span) |
+            InconsistentIndentation(span) |
+            InvalidCharacter(span, _) |
+            InvalidConversion(span) |
+            InvalidEscape(span) |
+            InvalidHex(span) |
+            InvalidIdentifier(span, _) |
+            InvalidDigit(span, _, _) |
+            InvalidNumber(span, _) |
+            InvalidUnderscore(span) |
+            InvalidOperator(span, _) |
+            InvalidPrefix(span, _) |
+            InvalidUnicode16(span) |
+            InvalidUnicode32(span) |
+            LeadingZeros(span) |
+            NonAsciiBytes(span) |
+            UnclosedDelimiter(span) |
+            UnclosedFormatField(span) |
+            UnknownUnicodeName(span, _) |
+            UnmatchedDedent(span) |
+            UnmatchedFormatBrace(span) |
+            UnterminatedString(span) => Some(span),
+        }
+    }
+}
+
+impl<'a> fmt::Display for LexerError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Continue => write!(f, "continue"),
+            Eof => write!(f, "unexpected end of file"),
+            EmptyFormatExpression(_) => {
+                write!(f, "f-string: empty expression not allowed")
+            }
+            InconsistentIndentation(_) => {
+                write!(f, "inconsistent use of tabs and spaces in indentation")
+            }
+            InvalidCharacter(_, ch) => write!(f, "invalid character {:?}", ch),
+            InvalidConversion(_) => {
+                write!(f, "f-string: invalid conversion character, expected 's', 'r', or 'a'")
+            }
+            InvalidEscape(_) => write!(f, "invalid escape sequence"),
+            InvalidHex(_) => write!(f, "truncated \\xXX escape"),
+            InvalidIdentifier(_, ch) => {
+                write!(f, "invalid character '{}' (U+{:04X})", ch, ch as u32)
+            }
+            InvalidDigit(_, ch, radix) => {
+                write!(f, "invalid digit '{}' in {} literal", ch, radix_name(radix))
+            }
+            InvalidNumber(_, radix) => write!(f, "invalid {} literal", radix_name(radix)),
+            InvalidUnderscore(_) => {
+                write!(f, "underscores in numbers must be between digits")
+            }
+            InvalidOperator(_, word) => write!(f, "invalid operator `{}`", word),
+            InvalidPrefix(_, word) => write!(f, "invalid string prefix `{}`", word),
+            InvalidUnicode16(_) => write!(f, "truncated \\uXXXX escape"),
+            InvalidUnicode32(_) => write!(f, "invalid \\UXXXXXXXX escape"),
+            LeadingZeros(_) => {
+                write!(f, "leading zeros in decimal integer literals are not \
+                           permitted; use an 0o prefix for octal integers")
+            }
+            NonAsciiBytes(_) => {
+                write!(f, "bytes can only contain ASCII literal characters")
+            }
+            UnclosedDelimiter(_) => write!(f, "unclosed delimiter"),
+            UnclosedFormatField(_) => write!(f, "f-string: expecting '}}'"),
+            UnknownUnicodeName(_, name) => {
+                write!(f, "unknown Unicode character name `{}`", name)
+            }
+            UnmatchedDedent(_) => {
+                write!(f, "unindent does not match any outer indentation level")
+            }
+            UnmatchedFormatBrace(_) => write!(f, "f-string: single '}}' is not allowed"),
+            UnterminatedString(_) => write!(f, "unterminated string literal"),
+        }
+    }
+}
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary",
+        8 => "octal",
+        16 => "hexadecimal",
+        _ => "decimal",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> (Vec<Lexeme>, Vec<LexerError>) {
+        let mut lexemes = Vec::new();
+        let mut errors = Vec::new();
+        for result in Lexer::new(source) {
+            match result {
+                Ok(lexeme) => lexemes.push(lexeme),
+                Err(error) => errors.push(error),
+            }
+        }
+        (lexemes, errors)
+    }
+
+    #[test]
+    fn streams_lines_lazily() {
+        let mut lexer = Lexer::new("a = 1\nb = $\nc = 3\n");
+
+        match lexer.next() {
+            Some(Ok(Lexeme::Identifier(_, ref name))) if name == "a" => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(lexer.pending.len(), 3);
+
+        let rest: Vec<_> = lexer.by_ref().skip(3).take(2).collect();
+        match &*rest {
+            [Err(InvalidCharacter(_, '$')), Ok(Lexeme::Identifier(_, name))] if name == "b" => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(lexer.offset < 14);
+        assert_eq!(lexer.count(), 6);
+    }
+
+    #[test]
+    fn recovers_after_errors() {
+        let source = "a = '\\xZZ'\nb = 1 $ 2\nc = 'open\nd = (1,\n";
+        let (lexemes, errors) = lex(source);
+
+        let errors: Vec<_> = errors.iter()
+                                   .map(|error| error.span().unwrap().start)
+                                   .collect();
+        assert_eq!(errors, vec![5, 17, 25, 35]);
+
+        let names: Vec<_> = lexemes.into_iter()
+                                 .filter_map(|lexeme| match lexeme {
+                                     Lexeme::Identifier(_, name) => Some(name),
+                                     _ => None,
+                                 })
+                                 .collect();
+        assert_eq!(names, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn unicode_names() {
+        let source = r#""\N{LATIN SMALL LETTER A}\N{hangul syllable gag}\N{CJK UNIFIED IDEOGRAPH-4E00}\N{LINE FEED}\N{bel}" "\N{NOT A NAME}""#;
+        let (lexemes, errors) = lex(source);
+
+        match &*errors {
+            [UnknownUnicodeName(_, "NOT A NAME")] => {}
+            errors => panic!("{:?}", errors),
+        }
+
+        match lexemes[0] {
+            Lexeme::Str(_, ref string) => assert_eq!(string, "a\u{AC01}\u{4E00}\n\u{7}"),
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    fn lex_one(source: &str) -> (Option<Lexeme>, Vec<String>) {
+        let (lexemes, errors) = lex(source);
+        let errors = errors.iter()
+                           .map(|error| format!("{:?}: {}", error.span().unwrap(), error))
+                           .collect();
+        (lexemes.into_iter().next(), errors)
+    }
+
+    #[test]
+    fn valid_numbers() {
+        let integers = [("0", "0"),
+                        ("00", "0"),
+                        ("0_0", "0"),
+                        ("1_000_000", "1000000"),
+                        ("123456789012345678901234567890", "123456789012345678901234567890"),
+                        ("0b1010", "10"),
+                        ("0B_1", "1"),
+                        ("0b1_0", "2"),
+                        ("0o17", "15"),
+                        ("0O_7_7", "63"),
+                        ("0xff", "255"),
+                        ("0X_DEAD_beef", "3735928559"),
+                        ("0xffff_ffff_ffff_ffff_ff", "4722366482869645213695")];
+
+        for &(source, value) in &integers {
+            match lex_one(source) {
+                (Some(Lexeme::Integer(span, ref number)), ref errors) if errors.is_empty() => {
+                    assert_eq!(span, Span::new(0, source.len()), "{}", source);
+                    assert_eq!(number.to_string(), value, "{}", source);
+                }
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let floats = [("1.5", 1.5),
+                      ("1.", 1.0),
+                      (".5", 0.5),
+                      ("0.5", 0.5),
+                      ("1e3", 1e3),
+                      ("1E+3", 1e3),
+                      ("1.e-2", 1e-2),
+                      ("1_0.0_1", 10.01),
+                      ("1_0e1_0", 10e10),
+                      ("012.5", 12.5),
+                      ("0e0", 0.0),
+                      ("09.", 9.0)];
+
+        for &(source, value) in &floats {
+            match lex_one(source) {
+                (Some(Lexeme::Float(span, number)), ref errors) if errors.is_empty() => {
+                    assert_eq!(span, Span::new(0, source.len()), "{}", source);
+                    assert_eq!(number, value, "{}", source);
+                }
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let imaginaries = [("1j", 1.0), (".5j", 0.5), ("012J", 12.0), ("1e2j", 1e2)];
+
+        for &(source, value) in &imaginaries {
+            match lex_one(source) {
+                (Some(Lexeme::Imaginary(span, number)), ref errors) if errors.is_empty() => {
+                    assert_eq!(span, Span::new(0, source.len()), "{}", source);
+                    assert_eq!(number, value, "{}", source);
+                }
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        // Some keywords can follow a number directly.
+        for &(source, keyword) in &[("1if", Keyword::If), ("1else", Keyword::Else),
+                                    ("0o7in", Keyword::In), ("1.5jor", Keyword::Or)] {
+            let (lexemes, errors) = lex(source);
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            match &lexemes[..2] {
+                [Lexeme::Integer(..), Lexeme::Keyword(_, found)] |
+                [Lexeme::Imaginary(..), Lexeme::Keyword(_, found)] => {
+                    assert_eq!(*found, keyword, "{}", source)
+                }
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        let cases = [("1_", "1..2: underscores in numbers must be between digits"),
+                     ("1__0", "1..2: underscores in numbers must be between digits"),
+                     ("0x_", "2..3: underscores in numbers must be between digits"),
+                     ("1._5", "0..3: invalid decimal literal"),
+                     ("0b", "0..2: invalid binary literal"),
+                     ("0b2", "2..3: invalid digit '2' in binary literal"),
+                     ("0b1_2", "4..5: invalid digit '2' in binary literal"),
+                     ("0o8", "2..3: invalid digit '8' in octal literal"),
+                     ("0xg", "0..3: invalid hexadecimal literal"),
+                     ("0b1j", "0..4: invalid binary literal"),
+                     ("012", "0..3: leading zeros in decimal integer literals are not \
+                              permitted; use an 0o prefix for octal integers"),
+                     ("1e", "0..2: invalid decimal literal"),
+                     ("1e+", "0..3: invalid decimal literal"),
+                     ("1a", "0..2: invalid decimal literal"),
+                     ("1iff", "0..2: invalid decimal literal")];
+
+        for &(source, message) in &cases {
+            let (lexeme, errors) = lex_one(source);
+            assert_eq!(errors, vec![message.to_owned()], "{}", source);
+            // The whole literal is skipped, nothing is left to lex as a name.
+            match lexeme {
+                None | Some(Lexeme::Newline(_)) => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let (lexemes, errors) = lex("x = 1_ + 2\n");
+        assert_eq!(errors.len(), 1);
+        let integers = lexemes.into_iter()
+                              .filter(|lexeme| match *lexeme {
+                                  Lexeme::Integer(..) => true,
+                                  _ => false,
+                              })
+                              .count();
+        assert_eq!(integers, 1);
+    }
+
+    /// The variant names of the lexemes, so layout tests stay readable.
+    fn layout(source: &str) -> (String, Vec<String>) {
+        let (lexemes, errors) = lex(source);
+        let kinds: Vec<_> = lexemes.iter()
+                                   .map(|lexeme| {
+                                       let debug = format!("{:?}", lexeme);
+                                       debug.split('(').next().unwrap().to_owned()
+                                   })
+                                   .collect();
+        let errors = errors.iter()
+                           .map(|error| format!("{:?}: {}", error.span().unwrap(), error))
+                           .collect();
+        (kinds.join(" "), errors)
+    }
+
+    #[test]
+    fn indentation() {
+        let (kinds, errors) = layout("if a:\n    b\n\n  # comment\n    c\n        d\ne\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(kinds,
+                   "Keyword Identifier Operator Newline \
+                    Indent Identifier Newline Identifier Newline \
+                    Indent Identifier Newline Dedent Dedent Identifier Newline");
+
+        // Blocks still open at the end of the file are closed.
+        let (kinds, errors) = layout("if a:\r\n    if b:\r\n        c");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(kinds,
+                   "Keyword Identifier Operator Newline \
+                    Indent Keyword Identifier Operator Newline \
+                    Indent Identifier Newline Dedent Dedent");
+
+        // A tab goes to the next multiple of eight.
+        let (kinds, errors) = layout("if a:\n  \tb\n\tc\n");
+        assert_eq!(kinds,
+                   "Keyword Identifier Operator Newline \
+                    Indent Identifier Newline Identifier Newline Dedent");
+        assert_eq!(errors,
+                   vec!["11..12: inconsistent use of tabs and spaces in indentation"]);
+
+        let (_, errors) = layout("if a:\n\tb\n        c\n");
+        assert_eq!(errors,
+                   vec!["9..17: inconsistent use of tabs and spaces in indentation"]);
+
+        let (kinds, errors) = layout("if a:\n    b\n  c\n  d\n");
+        assert_eq!(kinds,
+                   "Keyword Identifier Operator Newline \
+                    Indent Identifier Newline Identifier Newline \
+                    Identifier Newline Dedent");
+        assert_eq!(errors,
+                   vec!["12..14: unindent does not match any outer indentation level"]);
+    }
+
+    #[test]
+    fn operators() {
+        use lexeme::Operator::*;
+
+        let operators = [("+", Add), ("+=", AddAssign), ("-", Sub), ("-=", SubAssign),
+                         ("*", Mul), ("*=", MulAssign), ("**", Pow), ("**=", PowAssign),
+                         ("/", Div), ("/=", DivAssign), ("//", FloorDiv),
+                         ("//=", FloorDivAssign), ("%", Rem), ("%=", RemAssign),
+                         ("@", Dec), ("@=", DecAssign), ("&", And), ("&=", AndAssign),
+                         ("|", Or), ("|=", OrAssign), ("^", Xor), ("^=", XorAssign),
+                         ("~", Not), ("<<", Shl), ("<<=", ShlAssign), (">>", Shr),
+                         (">>=", ShrAssign), ("<", LessThan), ("<=", LessThanEqual),
+                         (">", MoreThan), (">=", MoreThanEqual), ("==", Equals),
+                         ("!=", NotEquals), ("=", Assign), (":=", Walrus), (":", Colon),
+                         (",", Sep), (";", Term), (".", Access), ("...", Ellipsis),
+                         ("->", FuncAnno)];
+
+        for &(source, operator) in &operators {
+            let (lexemes, errors) = lex(source);
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            match lexemes[0] {
+                Lexeme::Operator(span, lexed) => {
+                    assert_eq!(lexed, operator, "{}", source);
+                    assert_eq!(span, Span::new(0, source.len()), "{}", source);
+                }
+                ref other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let delimiters = [("()", Delimiter::ParenOpen, Delimiter::ParenClose),
+                          ("[]", Delimiter::ListOpen, Delimiter::ListClose),
+                          ("{}", Delimiter::DictOpen, Delimiter::DictClose)];
+
+        for &(source, open, close) in &delimiters {
+            let (lexemes, errors) = lex(source);
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            match (&lexemes[0], &lexemes[1]) {
+                (&Lexeme::Delimiter(_, first), &Lexeme::Delimiter(_, second)) => {
+                    assert_eq!((first, second), (open, close), "{}", source);
+                }
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn maximal_munch() {
+        use lexeme::Operator::*;
+
+        let sequences: [(&str, &[Operator]); 8] = [
+            ("**==", &[PowAssign, Assign]),
+            ("//==", &[FloorDivAssign, Assign]),
+            ("....", &[Ellipsis, Access]),
+            ("..", &[Access, Access]),
+            ("->=", &[FuncAnno, Assign]),
+            ("<<<=", &[Shl, LessThanEqual]),
+            (":==", &[Walrus, Assign]),
+            ("* *=", &[Mul, MulAssign]),
+        ];
+
+        for &(source, expected) in &sequences {
+            let (lexemes, errors) = lex(source);
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            let operators: Vec<_> = lexemes.iter()
+                                           .filter_map(|lexeme| match *lexeme {
+                                               Lexeme::Operator(_, operator) => Some(operator),
+                                               _ => None,
+                                           })
+                                           .collect();
+            assert_eq!(&*operators, expected, "{}", source);
+        }
+
+        let (_, errors) = lex("a ! b");
+        match &*errors {
+            [InvalidOperator(span, "!")] => assert_eq!(*span, Span::new(2, 3)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        let names = [("\u{FB01}le", "file"),
+                     ("\u{1D518}\u{1D52B}\u{1D526}", "Uni"),
+                     ("cafe\u{301}", "caf\u{E9}"),
+                     ("caf\u{E9}", "caf\u{E9}"),
+                     ("x\u{B2}", "x2"),
+                     ("_\u{3C0}", "_\u{3C0}"),
+                     ("\u{210C}", "H")];
+
+        for &(source, expected) in &names {
+            let (lexemes, errors) = lex(source);
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            match lexemes[0] {
+                Lexeme::Identifier(span, ref name) => {
+                    assert_eq!(name, expected);
+                    assert_eq!(span, Span::new(0, source.len()));
+                }
+                ref other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let invalid = [("x\u{20AC}y", "1..4: invalid character '\u{20AC}' (U+20AC)"),
+                       ("\u{B2}x", "0..2: invalid character '\u{B2}' (U+00B2)"),
+                       ("a = \u{201C}b\u{201D}", "4..7: invalid character '\u{201C}' (U+201C)")];
+
+        for &(source, message) in &invalid {
+            let (_, errors) = lex(source);
+            let errors: Vec<_> = errors.iter()
+                                       .map(|error| format!("{:?}: {}", error.span().unwrap(), error))
+                                       .collect();
+            assert_eq!(errors, vec![message], "{}", source);
+        }
+    }
+
+    #[test]
+    fn bytes() {
+        let literals: [(&str, &[u8]); 9] = [
+            (r#"b"\xff""#, b"\xff"),
+            (r#"b'\x00\x7f\x80'"#, b"\x00\x7f\x80"),
+            (r#"b"\377\0\12""#, b"\xff\x00\n"),
+            (r#"b"\777""#, b"\xff"),
+            (r#"B"\u00e9\N{DASH}""#, b"\\u00e9\\N{DASH}"),
+            (r#"rb"\xff\"""#, b"\\xff\\\""),
+            (r#"br'\n'"#, b"\\n"),
+            ("b'''a\nb'''", b"a\nb"),
+            (r#"b"\t\r\n\a\b\f\v\\\"""#, b"\t\r\n\x07\x08\x0c\x0b\\\""),
+        ];
+
+        for &(source, expected) in &literals {
+            let (lexemes, errors) = lex(source);
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            match lexemes[0] {
+                Lexeme::Bytes(span, ref bytes) => {
+                    assert_eq!(&**bytes, expected, "{}", source);
+                    assert_eq!(span, Span::new(0, source.len()));
+                }
+                ref other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let (lexemes, errors) = lex("b'caf\u{E9}!'");
+        match &*errors {
+            [NonAsciiBytes(span)] => assert_eq!(*span, Span::new(5, 7)),
+            other => panic!("{:?}", other),
+        }
+        match lexemes[0] {
+            Lexeme::Bytes(_, ref bytes) => assert_eq!(&**bytes, b"caf!"),
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn formatted_strings() {
+        // A single quote can't close a triple quoted f-string, so a string
+        // nested in a field can use the same quote.
+        let source = r#"f"""{d["k"]}""""#;
+        let (lexemes, errors) = lex(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        match lexemes[0] {
+            Lexeme::FormattedStr(span, ref parts) => {
+                assert_eq!(span, Span::new(0, source.len()));
+                match &**parts {
+                    [FormatPart::Field(ref field)] => assert_eq!(field.source, r#"d["k"]"#),
+                    other => panic!("{:?}", other),
+                }
+            }
+            ref other => panic!("{:?}", other),
+        }
+
+        // An invalid conversion is skipped over, leaving a single error.
+        let (_, errors) = lex(r#"f"{a!x}""#);
+        match &*errors {
+            [InvalidConversion(span)] => assert_eq!(*span, Span::new(4, 6)),
+            other => panic!("{:?}", other),
+        }
+    }
 }
@@ -0,0 +1,241 @@
+use lexeme::Lexeme;
+use lexer::{Lexer, LexerError};
+use span::Span;
+use symbols::*;
+
+/// Source text between lexemes that the parser doesn't need, but which is
+/// kept so the source can be rebuilt exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trivia {
+    /// A `#` comment, without the newline after it.
+    Comment(Span),
+    /// A backslash and the newline it joins onto the next line.
+    Continuation(Span),
+    /// A newline that doesn't end a logical line, after a blank line or
+    /// inside brackets.
+    Newline(Span),
+    /// Text the lexer reported an error for instead of lexing it.
+    Skipped(Span),
+    Whitespace(Span),
+}
+
+impl Trivia {
+    pub fn span(&self) -> Span {
+        match *self {
+            Trivia::Comment(span) |
+            Trivia::Continuation(span) |
+            Trivia::Newline(span) |
+            Trivia::Skipped(span) |
+            Trivia::Whitespace(span) => span,
+        }
+    }
+}
+
+/// A lexeme along with all the trivia since the one before it.
+#[derive(Clone, Debug)]
+pub struct Token<'a> {
+    pub leading: Vec<Trivia>,
+    pub lexeme: Lexeme<'a>,
+}
+
+impl<'a> Token<'a> {
+    /// The span of the token including its leading trivia.
+    pub fn full_span(&self) -> Span {
+        match self.leading.first() {
+            Some(trivia) => trivia.span().to(self.lexeme.span()),
+            None => self.lexeme.span(),
+        }
+    }
+}
+
+/// Wraps a `Lexer` so that every byte of the source belongs to a token,
+/// either as a lexeme or as trivia in front of one. The stream always ends
+/// with an `EndMarker`, which carries any trivia at the end of the file.
+/// Joining the text of every token's trivia and lexeme in order gives back
+/// the original source.
+pub struct Lossless<'a> {
+    finished: bool,
+    lexer: Lexer<'a>,
+    /// Byte offset just past the last lexeme yielded.
+    position: usize,
+    source: &'a str,
+}
+
+impl<'a> Lossless<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Lossless {
+            finished: false,
+            lexer: Lexer::new(source),
+            position: 0,
+            source: source,
+        }
+    }
+
+    fn token(&mut self, lexeme: Lexeme<'a>) -> Token<'a> {
+        let span = lexeme.span();
+        let leading = trivia(self.source, self.position, span.start);
+        self.position = self.position.max(span.end);
+
+        Token {
+            leading: leading,
+            lexeme: lexeme,
+        }
+    }
+}
+
+impl<'a> Iterator for Lossless<'a> {
+    type Item = Result<Token<'a>, LexerError<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match self.lexer.next() {
+            Some(Ok(lexeme)) => Some(Ok(self.token(lexeme))),
+            Some(Err(error)) => Some(Err(error)),
+            None => {
+                self.finished = true;
+                let end = Lexeme::EndMarker(Span::empty(self.source.len()));
+                Some(Ok(self.token(end)))
+            }
+        }
+    }
+}
+
+/// Splits `source[start..end]`, the text between two lexemes, into trivia.
+fn trivia(source: &str, start: usize, end: usize) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    if start >= end {
+        return trivia;
+    }
+
+    let text = &source[start..end];
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, ch)) = chars.next() {
+        let at = |offset: usize| start + offset;
+        let mut last = index + ch.len_utf8();
+
+        let piece = match ch {
+            '#' => {
+                while let Some(&(index, ch)) = chars.peek() {
+                    if ch == NEWLINE || ch == CARRIAGE {
+                        break;
+                    }
+                    last = index + ch.len_utf8();
+                    let _ = chars.next();
+                }
+                Trivia::Comment(Span::new(at(index), at(last)))
+            }
+            NEWLINE | CARRIAGE => {
+                if ch == CARRIAGE {
+                    if let Some(&(index, NEWLINE)) = chars.peek() {
+                        last = index + 1;
+                        let _ = chars.next();
+                    }
+                }
+                Trivia::Newline(Span::new(at(index), at(last)))
+            }
+            ESCAPE if newline_at(&text[last..]) > 0 => {
+                last += newline_at(&text[last..]);
+                while chars.peek().map_or(false, |&(index, _)| index < last) {
+                    let _ = chars.next();
+                }
+                Trivia::Continuation(Span::new(at(index), at(last)))
+            }
+            ch if ch.is_whitespace() => {
+                while let Some(&(index, ch)) = chars.peek() {
+                    if !ch.is_whitespace() || ch == NEWLINE || ch == CARRIAGE {
+                        break;
+                    }
+                    last = index + ch.len_utf8();
+                    let _ = chars.next();
+                }
+                Trivia::Whitespace(Span::new(at(index), at(last)))
+            }
+            _ => {
+                while let Some(&(index, ch)) = chars.peek() {
+                    if ch.is_whitespace() || ch == '#' || ch == ESCAPE {
+                        break;
+                    }
+                    last = index + ch.len_utf8();
+                    let _ = chars.next();
+                }
+                Trivia::Skipped(Span::new(at(index), at(last)))
+            }
+        };
+
+        trivia.push(piece);
+    }
+
+    trivia
+}
+
+/// The length of the newline `text` starts with, if any.
+fn newline_at(text: &str) -> usize {
+    if text.starts_with("\r\n") {
+        2
+    } else if text.starts_with('\n') || text.starts_with('\r') {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rebuild(source: &str) -> String {
+        let mut rebuilt = String::new();
+        for token in Lossless::new(source).filter_map(Result::ok) {
+            for trivia in &token.leading {
+                let span = trivia.span();
+                rebuilt.push_str(&source[span.start..span.end]);
+            }
+            let span = token.lexeme.span();
+            rebuilt.push_str(&source[span.start..span.end]);
+        }
+        rebuilt
+    }
+
+    #[test]
+    fn round_trips() {
+        let sources = ["",
+                       "x = 1\n",
+                       "x = 1",
+                       "# only a comment",
+                       "\n\n  \n# comment\nx = (1,  # inside\n     2)\n",
+                       "if a:\r\n\tb = 'c' \\\r\n + \"d\"\r\n\r\nelse:\n    pass\n",
+                       "y = f'{x!r:>{w}}' rb'\\x00'  \t# trailing\n",
+                       "x = 1 $ 2 ? 3\n",
+                       "def f():\n    return\n\x0c\n  # end",
+                       "s = '''a\n  b\n'''\n"];
+
+        for source in &sources {
+            assert_eq!(rebuild(source), *source);
+        }
+    }
+
+    #[test]
+    fn trivia_is_attached_to_the_next_token() {
+        let tokens: Vec<_> = Lossless::new("a  # note\n\\\nb").filter_map(Result::ok).collect();
+
+        match &*tokens[1].leading {
+            [Trivia::Whitespace(space), Trivia::Comment(comment)] => {
+                assert_eq!(*space, Span::new(1, 3));
+                assert_eq!(*comment, Span::new(3, 9));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match tokens.last() {
+            Some(&Token { lexeme: Lexeme::EndMarker(span), ref leading }) => {
+                assert_eq!(span, Span::empty(13));
+                assert!(leading.is_empty());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -1,19 +1,111 @@
 extern crate itertools;
+extern crate num_bigint;
+extern crate num_integer;
+extern crate num_traits;
 extern crate rustyline;
+extern crate unicode_normalization;
+extern crate unicode_xid;
 
+mod encoding;
 mod lexeme;
 mod lexer;
+mod lossless;
+mod span;
 mod symbols;
 mod ast;
 mod parser;
+mod relex;
 mod types;
 mod symbol_table;
+mod tokenize;
+mod unicode_names;
+
+use std::{env, fs, process};
 
 use lexer::Lexer;
 use parser::Parser;
+use span::LineTable;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|arg| &**arg) {
+        Some("tokenize") if args.len() == 2 => run_tokenize(&args[1]),
+        Some("tokenize") => {
+            eprintln!("usage: rython tokenize <file>");
+            process::exit(2);
+        }
+        Some(path) => run_file(path),
+        None => repl(),
+    }
+}
+
+/// Reads and decodes the file at `path`, exiting if either fails.
+fn read_source(path: &str) -> (Vec<u8>, String) {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let source = match encoding::decode(&bytes) {
+        Ok(source) => source.into_owned(),
+        Err(error) => {
+            eprintln!("{}:{}: {}", path, error.line(&bytes), error);
+            process::exit(1);
+        }
+    };
+
+    (bytes, source)
+}
+
+/// Prints the tokens of the file at `path` like `python -m tokenize`.
+fn run_tokenize(path: &str) {
+    let (bytes, source) = read_source(path);
+    // `read_source` has already checked the encoding.
+    let encoding = encoding::detect(&bytes).unwrap();
+    let name = tokenize::encoding_name(encoding);
+    print!("{}", tokenize::dump(&source, name));
+
+    let lines = LineTable::new(&source);
+    let mut failed = false;
+    for error in Lexer::new(&source).filter_map(Result::err) {
+        if let Some(span) = error.span() {
+            eprintln!("{}:{}: {}", path, lines.position(span.start), error);
+        }
+        failed = true;
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
 
+/// Decodes, lexes and parses the file at `path`, reporting any errors.
+fn run_file(path: &str) {
+    let (_, source) = read_source(path);
+
+    let lines = LineTable::new(&source);
+    let mut parser = Parser::new(Lexer::new(&source));
+    let result = parser.parse();
+    for error in parser.lex_errors() {
+        if let Some(span) = error.span() {
+            eprintln!("{}:{}: {}", path, lines.position(span.start), error);
+        }
+    }
+
+    match result {
+        Ok(()) => println!("{:?}", parser),
+        Err(_) if !parser.lex_errors().is_empty() => process::exit(1),
+        Err(error) => {
+            eprintln!("{}: {:?}", path, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn repl() {
     let mut rl = rustyline::Editor::<()>::new();
     loop {
         let string = rl.readline(">>> ");
@@ -26,10 +118,23 @@ fn main() {
             break;
         }
 
-        let mut lexer = Lexer::new(&string);
-        lexer.lex();
-        let mut parser = Parser::new(lexer.output());
-        parser.parse();
+        let lines = LineTable::new(&string);
+        let mut parser = Parser::new(Lexer::new(&string));
+        let result = parser.parse();
+        for error in parser.lex_errors() {
+            if let Some(span) = error.span() {
+                println!("{}: {}", lines.position(span.start), error);
+            }
+        }
+
+        match result {
+            Ok(()) => {}
+            Err(_) if !parser.lex_errors().is_empty() => continue,
+            Err(error) => {
+                println!("{:?}", error);
+                continue;
+            }
+        }
         println!("---------------------PARSER OUTPUT----------------------");
         println!("{:?}", parser.output());
     }
@@ -41,6 +146,7 @@ mod tests {
     extern crate walkdir;
     use self::walkdir::WalkDir;
 
+    use encoding;
     use lexer::Lexer;
     use parser::Parser;
     use std::fs::File;
@@ -55,14 +161,17 @@ mod tests {
             if file.file_type().is_dir() {
                 continue;
             }
-            let mut contents = String::new();
-            File::open(file.path()).unwrap().read_to_string(&mut contents).unwrap();
-            let mut lexer = Lexer::new(&contents);
-            lexer.lex();
+            let mut bytes = Vec::new();
+            File::open(file.path()).unwrap().read_to_end(&mut bytes).unwrap();
+            let contents = encoding::decode(&bytes).unwrap();
             println!("---------------------LEXER OUTPUT----------------------");
-            println!("{:?}", lexer);
-            let mut parser = Parser::new(lexer.output());
-            parser.parse();
+            for lexeme in Lexer::new(&contents) {
+                println!("{:?}", lexeme.unwrap());
+            }
+            let mut parser = Parser::new(Lexer::new(&contents));
+            if let Err(error) = parser.parse() {
+                panic!("{}: {:?}", file.path().display(), error);
+            }
             println!("---------------------PARSER OUTPUT---------------------");
             println!("{:#?}", parser);
         }
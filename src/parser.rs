@@ -1,12 +1,11 @@
 use std::{fmt, ops};
-use std::vec::IntoIter;
-
-use itertools::{self, MultiPeek};
+use std::collections::VecDeque;
 
 use ast::*;
-use lexeme::{Operator, Lexeme, Keyword};
+use lexeme::{Delimiter, FormatField, FormatPart, Operator, Lexeme, Keyword};
 use lexeme::Delimiter::*;
 use lexer::*;
+use span::Span;
 use self::ParseError::*;
 
 macro_rules! get_or_eof {
@@ -20,17 +19,74 @@ macro_rules! get_or_eof {
 
 pub type Result<'a, T: 'a> = ::std::result::Result<T, ParseError<'a>>;
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// The binary operators of `parse_binary`, from loosest to tightest, with how
+/// tightly they bind and which way they group. Adding an operator only takes
+/// a row here.
+const BINARY_OPERATORS: &'static [(Operator, u8, Associativity)] = &[
+    (Operator::Or, 1, Associativity::Left),
+    (Operator::Xor, 2, Associativity::Left),
+    (Operator::And, 3, Associativity::Left),
+    (Operator::Shl, 4, Associativity::Left),
+    (Operator::Shr, 4, Associativity::Left),
+    (Operator::Add, 5, Associativity::Left),
+    (Operator::Sub, 5, Associativity::Left),
+    (Operator::Mul, 6, Associativity::Left),
+    (Operator::Dec, 6, Associativity::Left),
+    (Operator::Div, 6, Associativity::Left),
+    (Operator::FloorDiv, 6, Associativity::Left),
+    (Operator::Rem, 6, Associativity::Left),
+    (Operator::Pow, 8, Associativity::Right),
+];
+
+/// The augmented assignment operators, with the binary operator each applies.
+const AUGMENTED_OPERATORS: &'static [(Operator, Operator)] = &[
+    (Operator::AddAssign, Operator::Add),
+    (Operator::AndAssign, Operator::And),
+    (Operator::DecAssign, Operator::Dec),
+    (Operator::DivAssign, Operator::Div),
+    (Operator::FloorDivAssign, Operator::FloorDiv),
+    (Operator::MulAssign, Operator::Mul),
+    (Operator::OrAssign, Operator::Or),
+    (Operator::PowAssign, Operator::Pow),
+    (Operator::RemAssign, Operator::Rem),
+    (Operator::ShlAssign, Operator::Shl),
+    (Operator::ShrAssign, Operator::Shr),
+    (Operator::SubAssign, Operator::Sub),
+    (Operator::XorAssign, Operator::Xor),
+];
+
+/// How tightly a prefix `+`, `-` or `~` binds: tighter than any binary
+/// operator except `**`.
+const UNARY_PRECEDENCE: u8 = 7;
+
+fn augmented_operator(operator: Operator) -> Option<Operator> {
+    AUGMENTED_OPERATORS.iter()
+                       .find(|&&(next, _)| next == operator)
+                       .map(|&(_, binary)| binary)
+}
+
+fn binary_operator(operator: Operator) -> Option<(u8, Associativity)> {
+    BINARY_OPERATORS.iter()
+                    .find(|&&(next, _, _)| next == operator)
+                    .map(|&(_, precedence, associativity)| (precedence, associativity))
+}
+
 pub struct Parser<'a>{
-    iter: MultiPeek<IntoIter<Lexeme<'a>>>,
+    iter: Tokens<'a>,
     output: Vec<Ast<'a>>
 }
 
 impl<'a> Parser<'a> {
 
-    pub fn new(vec: Vec<Lexeme<'a>>) -> Self {
+    pub fn new(lexer: Lexer<'a>) -> Self {
         Parser {
-            iter: itertools::multipeek(vec.into_iter()),
+            iter: Tokens::new(lexer),
             output: Vec::new(),
         }
     }
@@ -44,368 +100,2776 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse(&mut self) -> Result<'a, ()> {
-        while let Some(_) = self.peek() {
-            self.reset_peek();
+        while self.peek().is_some() {
+            let statements = self.parse_stmt()?;
+            self.check_lex_errors()?;
+            for statement in statements {
+                self.output.push(Ast::Statement(statement));
+            }
+        }
+        self.check_lex_errors()
+    }
+
+    /// Every error the lexer has reported in the lexemes read so far.
+    pub fn lex_errors(&self) -> &[LexerError<'a>] {
+        &self.iter.errors
+    }
 
-            let stmt = self.parse_stmt()?;
-            self.output.push(Ast::Statement(stmt));
+    fn check_lex_errors(&self) -> Result<'a, ()> {
+        match self.iter.errors.first() {
+            Some(error) => Err(LexError(error.clone())),
+            None => Ok(()),
         }
-        Ok(())
     }
 
-    fn parse_stmt(&mut self) -> Result<'a, Statement<'a>> {
-        let lhs = self.parse_test()?;
-        self.consume();
-        let rhs = self.parse_expr()?;
-        self.consume();
+    fn parse_stmt(&mut self) -> Result<'a, Vec<Statement<'a>>> {
+        let compound = match self.peek() {
+            Some(&Lexeme::Keyword(_, keyword)) => match keyword {
+                Keyword::Async | Keyword::Class | Keyword::Def | Keyword::For |
+                Keyword::If | Keyword::Try | Keyword::While | Keyword::With => true,
+                _ => false,
+            },
+            Some(&Lexeme::Operator(_, Operator::Dec)) => true,
+            _ => false,
+        };
 
-        Ok(Statement::Assignment(lhs, rhs))
+        if compound {
+            Ok(vec![Statement::Compound(self.parse_compound_stmt()?)])
+        } else {
+            self.parse_simple_stmt()
+        }
     }
 
-    fn parse_comparison(&mut self) -> Result<'a, Expression<'a>> {
-        use lexeme::Keyword::*;
-        let lhs = self.parse_expr()?;
+    fn parse_compound_stmt(&mut self) -> Result<'a, CompoundStatement<'a>> {
+        let mut decorators = Vec::new();
+        while self.eat_operator(Operator::Dec) {
+            decorators.push(self.parse_named_expr()?);
+            match get_or_eof!(self.next()) {
+                Lexeme::Newline(_) => {}
+                lexeme => return Err(UnexpectedToken(lexeme)),
+            }
+        }
 
-        let comp = match self.peek() {
-            Some(&Lexeme::Operator(operator)) if operator.is_comp_op() => {
-                self.consume();
-                Comparison::Op(lhs, operator, self.parse_expr()?)
+        let (span, mut keyword) = match get_or_eof!(self.next()) {
+            Lexeme::Keyword(span, keyword) => (span, keyword),
+            lexeme => return Err(UnexpectedToken(lexeme)),
+        };
+
+        let is_async = keyword == Keyword::Async;
+        if is_async {
+            keyword = match get_or_eof!(self.next()) {
+                Lexeme::Keyword(_, keyword @ Keyword::Def) |
+                Lexeme::Keyword(_, keyword @ Keyword::For) |
+                Lexeme::Keyword(_, keyword @ Keyword::With) => keyword,
+                lexeme => return Err(UnexpectedToken(lexeme)),
+            };
+        }
+
+        let statement = match keyword {
+            Keyword::Class => {
+                let name = self.parse_name()?;
+                let arguments = if self.eat_delimiter(ParenOpen) {
+                    let arguments = self.parse_arg_list()?;
+                    self.expect_delimiter(ParenClose)?;
+                    arguments
+                } else {
+                    Vec::new()
+                };
+                CompoundStatement::ClassDef(ClassDef {
+                    arguments: arguments,
+                    body: self.parse_suite()?,
+                    decorators: decorators,
+                    name: name,
+                    span: span,
+                })
             }
-            Some(&Lexeme::Keyword(_, keyword)) if keyword.is_comp_keyword() => {
-                self.consume();
-                let keyword = if keyword == Is {
-                    if let Lexeme::Keyword(_, Not) = *self.peek().expect(&format!("{}", line!())) {
-                        IsNot
+            Keyword::Def => {
+                let name = self.parse_name()?;
+                self.expect_delimiter(ParenOpen)?;
+                let parameters = self.parse_parameters(true)?;
+                self.expect_delimiter(ParenClose)?;
+                let returns = if self.eat_operator(Operator::FuncAnno) {
+                    Some(self.parse_test()?)
+                } else {
+                    None
+                };
+                CompoundStatement::FunctionDef(FunctionDef {
+                    body: self.parse_suite()?,
+                    decorators: decorators,
+                    is_async: is_async,
+                    name: name,
+                    parameters: parameters,
+                    returns: returns,
+                    span: span,
+                })
+            }
+            _ if !decorators.is_empty() => {
+                return Err(UnexpectedToken(Lexeme::Keyword(span, keyword)));
+            }
+            Keyword::For => {
+                let target = self.parse_target_list()?;
+                check_target(&target, false)?;
+                self.expect_keyword(Keyword::In)?;
+                let iterable = self.parse_test_list()?;
+                let body = self.parse_suite()?;
+                CompoundStatement::For(For {
+                    body: body,
+                    else_case: self.parse_else()?,
+                    is_async: is_async,
+                    iterable: iterable,
+                    span: span,
+                    target: target,
+                })
+            }
+            Keyword::If => {
+                let expression = self.parse_named_expr()?;
+                let body = self.parse_suite()?;
+                let mut elif_cases = Vec::new();
+                while self.eat_keyword(Keyword::Elif) {
+                    let expression = self.parse_named_expr()?;
+                    elif_cases.push((expression, self.parse_suite()?));
+                }
+                CompoundStatement::If(If {
+                    body: body,
+                    elif_cases: elif_cases,
+                    else_case: self.parse_else()?,
+                    expression: expression,
+                    span: span,
+                })
+            }
+            Keyword::Try => CompoundStatement::Try(self.parse_try(span)?),
+            Keyword::While => {
+                let expression = self.parse_named_expr()?;
+                let body = self.parse_suite()?;
+                CompoundStatement::While(While {
+                    body: body,
+                    else_case: self.parse_else()?,
+                    expression: expression,
+                    span: span,
+                })
+            }
+            Keyword::With => {
+                let items = self.parse_with_items()?;
+                CompoundStatement::With(With {
+                    body: self.parse_suite()?,
+                    is_async: is_async,
+                    items: items,
+                    span: span,
+                })
+            }
+            _ => return Err(UnexpectedToken(Lexeme::Keyword(span, keyword))),
+        };
+
+        Ok(statement)
+    }
+
+    /// A parameter list, up to the first lexeme that can't be part of one.
+    /// Parameters can only have annotations if `annotated` is set, as in a
+    /// `def` but not a `lambda`.
+    fn parse_parameters(&mut self, annotated: bool) -> Result<'a, Parameters<'a>> {
+        let mut parameters = Parameters::default();
+        let mut slash = false;
+        let mut star = false;
+        // A bare `*` has to be followed by a keyword-only parameter.
+        let mut bare_star = None;
+
+        loop {
+            let next = match self.peek() {
+                Some(lexeme) => lexeme.clone(),
+                None => break,
+            };
+
+            match next {
+                Lexeme::Operator(_, Operator::Div) => {
+                    if slash || star || parameters.positional.is_empty() {
+                        return Err(UnexpectedToken(next));
+                    }
+                    self.consume();
+                    slash = true;
+                    parameters.positional_only = parameters.positional.split_off(0);
+                }
+                Lexeme::Operator(_, Operator::Mul) => {
+                    if star {
+                        return Err(UnexpectedToken(next));
+                    }
+                    self.consume();
+                    star = true;
+                    if self.at_name() {
+                        parameters.varargs = Some(self.parse_parameter(annotated, false)?);
                     } else {
-                        keyword
+                        bare_star = Some(next);
                     }
-                } else if keyword == Not {
-                    if let Lexeme::Keyword(_, In) = *self.peek().expect(&format!("{}", line!())) {
-                        NotIn
+                }
+                Lexeme::Operator(_, Operator::Pow) => {
+                    if let Some(star) = bare_star {
+                        return Err(UnexpectedToken(star));
+                    }
+                    self.consume();
+                    parameters.kwargs = Some(self.parse_parameter(annotated, false)?);
+                    self.eat_operator(Operator::Sep);
+                    break;
+                }
+                Lexeme::Identifier(..) => {
+                    let parameter = self.parse_parameter(annotated, true)?;
+                    if star {
+                        bare_star = None;
+                        parameters.keyword_only.push(parameter);
                     } else {
-                        keyword
+                        // Once one parameter has a default, the rest before
+                        // the `*` need one too.
+                        let defaulted = parameters.positional_only.iter()
+                                                  .chain(&parameters.positional)
+                                                  .any(|before| before.default.is_some());
+                        if defaulted && parameter.default.is_none() {
+                            return Err(UnexpectedToken(next));
+                        }
+                        parameters.positional.push(parameter);
                     }
-                } else {
-                    keyword
-                };
+                }
+                _ => break,
+            }
 
-                Comparison::Keyword(lhs, keyword, self.parse_expr()?)
+            if !self.eat_operator(Operator::Sep) {
+                break;
             }
-            _ => Comparison::Truthy(lhs)
-        };
+        }
 
-        Ok(Expression::Comparison(Box::new(comp)))
+        match bare_star {
+            Some(star) => Err(UnexpectedToken(star)),
+            None => Ok(parameters),
+        }
     }
 
-    fn parse_not_test(&mut self) -> Result<'a, Expression<'a>> {
-        use lexeme::Keyword::*;
-        if let Some(&Lexeme::Keyword(_, Not)) = self.peek() {
-            self.consume();
-            let comp = Box::new(Comparison::Notty(self.parse_not_test()?));
-            Ok(Expression::Comparison(comp))
+    fn parse_parameter(&mut self, annotated: bool, default: bool)
+        -> Result<'a, Parameter<'a>>
+    {
+        let name = self.parse_name()?;
+        let annotation = if annotated && self.eat_operator(Operator::Colon) {
+            Some(self.parse_test()?)
         } else {
-            self.parse_comparison()
+            None
+        };
+        let default = if default && self.eat_operator(Operator::Assign) {
+            Some(self.parse_test()?)
+        } else {
+            None
+        };
+        Ok(Parameter { annotation: annotation, default: default, name: name })
+    }
+
+    /// The arguments between the parentheses of a call or a class's bases.
+    /// Positional arguments can't follow keyword ones, and `*iterable` can't
+    /// follow `**mapping`.
+    fn parse_arg_list(&mut self) -> Result<'a, Vec<Argument<'a>>> {
+        let mut arguments = Vec::new();
+        let mut keyword = false;
+        let mut double_starred = false;
+
+        loop {
+            let next = match self.peek() {
+                Some(&Lexeme::Delimiter(_, ParenClose)) | None => break,
+                Some(lexeme) => lexeme.clone(),
+            };
+
+            let argument = match next {
+                Lexeme::Operator(_, Operator::Mul) => {
+                    if double_starred {
+                        return Err(UnexpectedToken(next));
+                    }
+                    self.consume();
+                    Argument::Starred(self.parse_test()?)
+                }
+                Lexeme::Operator(_, Operator::Pow) => {
+                    self.consume();
+                    keyword = true;
+                    double_starred = true;
+                    Argument::DoubleStarred(self.parse_test()?)
+                }
+                Lexeme::Identifier(..) if self.peek_nth(1).map_or(false, |next| match *next {
+                    Lexeme::Operator(_, Operator::Assign) => true,
+                    _ => false,
+                }) => {
+                    let name = self.parse_name()?;
+                    self.consume();
+                    keyword = true;
+                    Argument::Keyword(name, self.parse_test()?)
+                }
+                _ => {
+                    if keyword {
+                        return Err(UnexpectedToken(next));
+                    }
+                    let argument = self.parse_named_expr()?;
+                    if self.at_comp_for() {
+                        // A generator expression only needs its own
+                        // parentheses if it isn't the only argument.
+                        if !arguments.is_empty() {
+                            return Err(UnexpectedToken(get_or_eof!(self.next())));
+                        }
+                        let start = next.span();
+                        let clauses = self.parse_comp_for(&argument)?;
+                        let comprehension = Comprehension::Generator(argument, clauses);
+                        check_comprehension(&comprehension)?;
+                        let end = match self.peek() {
+                            Some(&Lexeme::Delimiter(span, ParenClose)) => span,
+                            _ => return Err(UnexpectedToken(get_or_eof!(self.next()))),
+                        };
+                        let atom = Atom::Comprehension(start.to(end), Box::new(comprehension));
+                        arguments.push(Argument::Positional(new_atom(atom)));
+                        break;
+                    }
+                    Argument::Positional(argument)
+                }
+            };
+            arguments.push(argument);
+
+            if !self.eat_operator(Operator::Sep) {
+                break;
+            }
         }
+
+        Ok(arguments)
     }
 
-    fn parse_and_test(&mut self) -> Result<'a, Expression<'a>> {
-        use lexeme::Keyword::*;
-        let lhs = self.parse_not_test()?;
+    /// What follows `try`, up to the end of the last clause. There has to be
+    /// at least one `except` or a `finally`, and `except` can't be mixed with
+    /// `except*`.
+    fn parse_try(&mut self, span: Span) -> Result<'a, Try<'a>> {
+        let body = self.parse_suite()?;
+        let mut handlers = Vec::new();
+        let mut star = false;
+        let mut bare = false;
 
-        let comp = if let Some(&Lexeme::Keyword(_, And)) = self.peek() {
+        while let Some(&Lexeme::Keyword(except, Keyword::Except)) = self.peek() {
             self.consume();
-            let rhs = self.parse_not_test()?;
-            Expression::Comparison(Box::new(Comparison::Keyword(lhs, And, rhs)))
+            // A bare `except:` has to be the last handler.
+            if bare {
+                return Err(UnexpectedToken(Lexeme::Keyword(except, Keyword::Except)));
+            }
+
+            let is_star = self.eat_operator(Operator::Mul);
+            if !handlers.is_empty() && is_star != star {
+                return Err(UnexpectedToken(Lexeme::Keyword(except, Keyword::Except)));
+            }
+            star = is_star;
+
+            let expression = if is_star || self.at_expression() {
+                Some(self.parse_test()?)
+            } else {
+                None
+            };
+            bare = expression.is_none();
+            let name = if expression.is_some() && self.eat_keyword(Keyword::As) {
+                Some(self.parse_name()?)
+            } else {
+                None
+            };
+
+            handlers.push(ExceptHandler {
+                body: self.parse_suite()?,
+                expression: expression,
+                name: name,
+                span: except,
+            });
+        }
+
+        let else_case = if handlers.is_empty() {
+            None
+        } else {
+            self.parse_else()?
+        };
+        let finally = if self.eat_keyword(Keyword::Finally) {
+            Some(self.parse_suite()?)
         } else {
-            lhs
+            None
         };
 
-        Ok(comp)
+        if handlers.is_empty() && finally.is_none() {
+            return match self.next() {
+                Some(lexeme) => Err(UnexpectedToken(lexeme)),
+                None => Err(Eof),
+            };
+        }
+
+        Ok(Try {
+            body: body,
+            else_case: else_case,
+            finally: finally,
+            handlers: handlers,
+            span: span,
+            star: star,
+        })
     }
 
-    fn parse_or_test(&mut self) -> Result<'a, Expression<'a>> {
-        use lexeme::Keyword::*;
-        let lhs = self.parse_and_test()?;
+    /// The items of a `with`, which can be wrapped in parentheses. Whether
+    /// an opening parenthesis is that or the start of the first expression
+    /// depends on whether its match is followed by the `:`.
+    fn parse_with_items(&mut self) -> Result<'a, Vec<WithItem<'a>>> {
+        let close = match self.peek() {
+            Some(&Lexeme::Delimiter(_, ParenOpen)) => self.find_closing(0),
+            _ => None,
+        };
+        let parenthesized = match close.and_then(|close| self.peek_nth(close + 1)) {
+            Some(&Lexeme::Operator(_, Operator::Colon)) => true,
+            _ => false,
+        };
 
-        let comp = if let Some(&Lexeme::Keyword(_, Or)) = self.peek() {
+        if parenthesized {
             self.consume();
-            let rhs = self.parse_and_test()?;
-            Expression::Comparison(Box::new(Comparison::Keyword(lhs, Or, rhs)))
-        } else {
-            lhs
-        };
+        }
 
-        Ok(comp)
+        let mut items = Vec::new();
+        loop {
+            let expression = self.parse_test()?;
+            let target = if self.eat_keyword(Keyword::As) {
+                let target = self.parse_expr()?;
+                check_target(&target, false)?;
+                Some(target)
+            } else {
+                None
+            };
+            items.push(WithItem { expression: expression, target: target });
+
+            if !self.eat_operator(Operator::Sep) ||
+               (parenthesized && !self.at_expression()) {
+                break;
+            }
+        }
+
+        if parenthesized {
+            self.expect_delimiter(ParenClose)?;
+        }
+        Ok(items)
     }
 
-    fn parse_test(&mut self) -> Result<'a, Expression<'a>> {
-        self.parse_or_test()
+    /// How far ahead the delimiter closing the one `open` places ahead is,
+    /// if it's on the same logical line.
+    fn find_closing(&mut self, open: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut index = open;
+        loop {
+            match self.peek_nth(index) {
+                Some(&Lexeme::Delimiter(_, delimiter)) => {
+                    match delimiter {
+                        DictOpen | ListOpen | ParenOpen => depth += 1,
+                        DictClose | ListClose | ParenClose => depth -= 1,
+                    }
+                    if depth == 0 {
+                        return Some(index);
+                    }
+                }
+                Some(&Lexeme::Newline(_)) | None => return None,
+                _ => {}
+            }
+            index += 1;
+        }
     }
 
-    fn parse_await(&mut self) -> Result<'a, Expression<'a>> {
-        if let Some(&Lexeme::Keyword(_, Keyword::Await)) = self.peek() {
-            self.consume();
-            Ok(Expression::Await(Box::new(self.parse_primary()?)))
+    /// An optional `else` clause.
+    fn parse_else(&mut self) -> Result<'a, Option<Suite<'a>>> {
+        if self.eat_keyword(Keyword::Else) {
+            Ok(Some(self.parse_suite()?))
         } else {
-            self.parse_primary()
+            Ok(None)
         }
     }
 
-    fn parse_primary(&mut self) -> Result<'a, Expression<'a>> {
-        let atom = self.parse_atom()?;
-
+    /// The `:` and block of a compound statement, which is either the rest of
+    /// the line or an indented block on the lines after it.
+    fn parse_suite(&mut self) -> Result<'a, Suite<'a>> {
+        self.expect_operator(Operator::Colon)?;
         match self.peek() {
-            Some(&Lexeme::Delimiter(_, ParenOpen)) => {
-                if atom.is_number() {
-                    panic!("Number's aren't callable.")
-                }
-                self.consume();
-                let arg_list = self.parse_arg_list()?;
+            Some(&Lexeme::Newline(_)) => self.consume(),
+            _ => return self.parse_simple_stmt(),
+        }
+
+        match get_or_eof!(self.next()) {
+            Lexeme::Indent(_) => {}
+            lexeme => return Err(UnexpectedToken(lexeme)),
+        }
+
+        let mut statements = Vec::new();
+        loop {
+            match self.peek() {
+                Some(&Lexeme::Dedent(_)) => break,
+                Some(_) => {}
+                None => return Err(Eof),
+            }
+            statements.extend(self.parse_stmt()?);
+        }
+        self.consume();
+
+        Ok(statements)
+    }
+
+    /// Small statements separated by `;`, up to the end of the line.
+    fn parse_simple_stmt(&mut self) -> Result<'a, Vec<Statement<'a>>> {
+        let mut statements = vec![self.parse_small_stmt()?];
+        loop {
+            match self.next() {
+                Some(Lexeme::Operator(_, Operator::Term)) => match self.peek() {
+                    Some(&Lexeme::Newline(_)) | None => {}
+                    _ => statements.push(self.parse_small_stmt()?),
+                },
+                Some(Lexeme::Newline(_)) | None => return Ok(statements),
+                Some(lexeme) => return Err(UnexpectedToken(lexeme)),
+            }
+        }
+    }
 
-                if let Some(Lexeme::Delimiter(_, ParenClose)) = self.next() {
-                    Ok(Expression::Primary(Primary::new_call(atom, ())))
+    fn parse_small_stmt(&mut self) -> Result<'a, Statement<'a>> {
+        let (span, keyword) = match self.peek() {
+            Some(&Lexeme::Keyword(span, keyword)) => (span, keyword),
+            _ => return self.parse_expr_stmt(),
+        };
+
+        let statement = match keyword {
+            Keyword::Assert => {
+                self.consume();
+                let test = self.parse_test()?;
+                let message = if self.eat_operator(Operator::Sep) {
+                    Some(self.parse_test()?)
                 } else {
-                    panic!("Incorrect param");
+                    None
+                };
+                Statement::Assert(span, test, message)
+            }
+            Keyword::Break => {
+                self.consume();
+                Statement::Break(span)
+            }
+            Keyword::Continue => {
+                self.consume();
+                Statement::Continue(span)
+            }
+            Keyword::Del => {
+                self.consume();
+                let targets = self.parse_expr_list()?;
+                for target in &targets {
+                    check_delete_target(target)?;
                 }
+                Statement::Delete(span, targets)
             }
-
-            Some(&Lexeme::Delimiter(_, ListOpen)) => {
+            Keyword::From => {
+                self.consume();
+                Statement::ImportFrom(span, self.parse_import_from()?)
+            }
+            Keyword::Global => {
+                self.consume();
+                Statement::Global(span, self.parse_names()?)
+            }
+            Keyword::Import => {
                 self.consume();
-                if atom.is_number() {
-                    panic!("Number's aren't subscribable.")
+                let mut aliases = vec![self.parse_alias(true)?];
+                while self.eat_operator(Operator::Sep) {
+                    aliases.push(self.parse_alias(true)?);
                 }
-                let sub_list = self.parse_sub_list()?;
-
-                if let Some(Lexeme::Delimiter(_, ListClose)) = self.next() {
-                    Ok(Expression::Primary(Primary::new_subscription(atom, ())))
+                Statement::Import(span, aliases)
+            }
+            Keyword::NonLocal => {
+                self.consume();
+                Statement::NonLocal(span, self.parse_names()?)
+            }
+            Keyword::Pass => {
+                self.consume();
+                Statement::Pass(span)
+            }
+            Keyword::Raise => {
+                self.consume();
+                if self.at_expression() {
+                    let exception = self.parse_test()?;
+                    let cause = if self.eat_keyword(Keyword::From) {
+                        Some(self.parse_test()?)
+                    } else {
+                        None
+                    };
+                    Statement::Raise(span, Some(exception), cause)
                 } else {
-                    panic!("No List close");
+                    Statement::Raise(span, None, None)
                 }
             }
-
-            Some(&Lexeme::Operator(Operator::Access)) => {
+            Keyword::Return => {
                 self.consume();
-                let primary = self.parse_primary()?;
-                Ok(Expression::Primary(Primary::new_attribute_ref(atom, primary)?))
+                let value = if self.at_expression() {
+                    Some(self.parse_test_list()?)
+                } else {
+                    None
+                };
+                Statement::Return(span, value)
             }
+            _ => return self.parse_expr_stmt(),
+        };
 
-            _ => {
-                self.reset_peek();
-                Ok(atom)
-            }
-        }
+        Ok(statement)
     }
 
-    fn parse_arg_list(&mut self) -> Result<'a, Expression<'a>> {
-        unimplemented!()
+    /// An expression on its own, or an assignment to it.
+    fn parse_expr_stmt(&mut self) -> Result<'a, Statement<'a>> {
+        let first = self.parse_yield_or_test_list()?;
+
+        let augmented = match self.peek() {
+            Some(&Lexeme::Operator(_, operator)) => augmented_operator(operator),
+            _ => None,
+        };
+        if let Some(operator) = augmented {
+            self.consume();
+            check_single_target(&first)?;
+            let value = self.parse_yield_or_test_list()?;
+            self.check_not_starred(&value)?;
+            return Ok(Statement::AugAssign(first, operator, value));
+        }
+
+        if self.eat_operator(Operator::Colon) {
+            check_single_target(&first)?;
+            let annotation = self.parse_test()?;
+            let value = if self.eat_operator(Operator::Assign) {
+                let value = self.parse_yield_or_test_list()?;
+                self.check_not_starred(&value)?;
+                Some(value)
+            } else {
+                None
+            };
+            return Ok(Statement::AnnAssign(first, annotation, value));
+        }
+
+        if !self.at_operator(Operator::Assign) {
+            self.check_not_starred(&first)?;
+            return Ok(Statement::Expression(first));
+        }
+
+        let mut targets = vec![first];
+        while self.eat_operator(Operator::Assign) {
+            targets.push(self.parse_yield_or_test_list()?);
+        }
+        let value = targets.pop().unwrap();
+        for target in &targets {
+            check_target(target, false)?;
+        }
+        self.check_not_starred(&value)?;
+        Ok(Statement::Assignment(targets, value))
     }
 
-    fn parse_sub_list(&mut self) -> Result<'a, Expression<'a>> {
-        unimplemented!()
+    /// A starred expression has to be in a tuple, as in `x = *a,` or
+    /// `(*a,)`, it can't be used on its own.
+    fn check_not_starred(&mut self, expression: &Expression<'a>) -> Result<'a, ()> {
+        match *expression {
+            Expression::Starred(_) => Err(UnexpectedToken(get_or_eof!(self.next()))),
+            _ => Ok(()),
+        }
     }
 
-    fn parse_atom(&mut self) -> Result<'a, Expression<'a>> {
-        let atom: Atom = match self.next().expect(&format!("{}", line!())) {
-            Lexeme::Identifier(index, name) => {
-                Atom::Identifier(index, name)
+    /// What follows `from`: the module, with any leading dots, and the names
+    /// imported from it.
+    fn parse_import_from(&mut self) -> Result<'a, ImportFrom<'a>> {
+        let mut level = 0;
+        loop {
+            match self.peek() {
+                Some(&Lexeme::Operator(_, Operator::Access)) => level += 1,
+                // `...` is lexed as a single operator.
+                Some(&Lexeme::Operator(_, Operator::Ellipsis)) => level += 3,
+                _ => break,
             }
+            self.consume();
+        }
 
-            Lexeme::Str(index, mut string) => {
-                let mut count = 0;
-                while let Some(&Lexeme::Str(_, ref next)) = self.peek() {
-                    count += 1;
-                    string.push_str(next);
-                }
+        let module = match self.peek() {
+            Some(&Lexeme::Keyword(_, Keyword::Import)) if level > 0 => Vec::new(),
+            _ => self.parse_dotted_name()?,
+        };
+        self.expect_keyword(Keyword::Import)?;
 
-                for _ in 0..count {
-                    self.consume();
-                }
+        if self.eat_operator(Operator::Mul) {
+            return Ok(ImportFrom { level: level, module: module, names: None });
+        }
 
-                Atom::Literal(Lexeme::Str(index, string))
+        let parenthesized = self.eat_delimiter(ParenOpen);
+        let mut names = vec![self.parse_alias(false)?];
+        while self.eat_operator(Operator::Sep) {
+            // A trailing comma is only allowed inside parentheses.
+            if parenthesized && !self.at_name() {
+                break;
             }
+            names.push(self.parse_alias(false)?);
+        }
+        if parenthesized {
+            self.expect_delimiter(ParenClose)?;
+        }
 
-            Lexeme::Bytes(index, mut bytes) => {
-                let mut count = 0;
-                while let Some(&Lexeme::Bytes(_, ref next)) = self.peek() {
-                    count += 1;
-                    bytes.extend(next);
-                }
+        Ok(ImportFrom { level: level, module: module, names: Some(names) })
+    }
 
-                for _ in 0..count {
-                    self.consume();
-                }
+    /// `name [as alias]`, where `name` may be dotted if `dotted` is set.
+    fn parse_alias(&mut self, dotted: bool) -> Result<'a, Alias<'a>> {
+        let path = if dotted {
+            self.parse_dotted_name()?
+        } else {
+            vec![self.parse_name()?]
+        };
+        let alias = if self.eat_keyword(Keyword::As) {
+            Some(self.parse_name()?)
+        } else {
+            None
+        };
+        Ok(Alias { alias: alias, path: path })
+    }
 
-                Atom::Literal(Lexeme::Bytes(index, bytes))
-            }
+    fn parse_dotted_name(&mut self) -> Result<'a, Vec<Name<'a>>> {
+        let mut names = vec![self.parse_name()?];
+        while self.eat_operator(Operator::Access) {
+            names.push(self.parse_name()?);
+        }
+        Ok(names)
+    }
 
-            token @ Lexeme::Float(_) | token @ Lexeme::Integer(_) => {
-                Atom::Literal(token)
-            }
+    /// Comma separated names, as in `global` and `nonlocal`.
+    fn parse_names(&mut self) -> Result<'a, Vec<Name<'a>>> {
+        let mut names = vec![self.parse_name()?];
+        while self.eat_operator(Operator::Sep) {
+            names.push(self.parse_name()?);
+        }
+        Ok(names)
+    }
 
-            Lexeme::Delimiter(_, ParenOpen) => {
-                unimplemented!();
-            },
+    fn parse_name(&mut self) -> Result<'a, Name<'a>> {
+        match get_or_eof!(self.next()) {
+            Lexeme::Identifier(span, name) => Ok(Name { name: name, span: span }),
+            lexeme => Err(UnexpectedToken(lexeme)),
+        }
+    }
 
-            Lexeme::Delimiter(_, ListOpen) => {
-                unimplemented!();
-            }
+    /// Comma separated expressions, which make a tuple if there's more than
+    /// one or a trailing comma.
+    fn parse_test_list(&mut self) -> Result<'a, Expression<'a>> {
+        let first = self.parse_star_or_test()?;
+        if !self.at_operator(Operator::Sep) {
+            return Ok(first);
+        }
 
-            Lexeme::Delimiter(_, DictOpen) => {
-                unimplemented!();
-            }
+        let mut items = vec![first];
+        while self.eat_operator(Operator::Sep) && self.at_expression() {
+            items.push(self.parse_star_or_test()?);
+        }
+        Ok(Expression::Tuple(items))
+    }
 
-            Lexeme::Keyword(_, Keyword::Yield) => {
-                unimplemented!();
-            }
-            other => panic!("{:?}", other),
-        };
+    /// A `yield` expression, which can stand on its own as a statement or as
+    /// the value of an assignment, or otherwise a `parse_test_list`.
+    fn parse_yield_or_test_list(&mut self) -> Result<'a, Expression<'a>> {
+        if self.at_keyword(Keyword::Yield) {
+            self.parse_yield()
+        } else {
+            self.parse_test_list()
+        }
+    }
 
-        Ok(Expression::Primary(Primary::Atom(atom)))
+    /// `yield` followed by what it yields, if anything, or `yield from` an
+    /// iterable.
+    fn parse_yield(&mut self) -> Result<'a, Expression<'a>> {
+        self.expect_keyword(Keyword::Yield)?;
+
+        let atom = if self.eat_keyword(Keyword::From) {
+            Atom::YieldFrom(Box::new(self.parse_test()?))
+        } else if self.at_expression() {
+            Atom::Yield(Some(Box::new(self.parse_test_list()?)))
+        } else {
+            Atom::Yield(None)
+        };
+        Ok(new_atom(atom))
     }
 
-    fn parse_pow(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_await()?;
-        match *self.peek().expect(&format!("{}", line!())) {
-            Lexeme::Operator(op @ Operator::Pow) => {
-                self.consume();
-                let is_unary = self.peek().expect(&format!("{}", line!())).is_unary();
-                let rhs = if is_unary {
-                    self.parse_unary()?
-                } else {
-                    self.parse_primary()?
-                };
-                Ok(Expression::new_binary_op(lhs, op, rhs))
-            }
-            _ => Ok(lhs)
+    /// The targets of a `for`, which make a tuple like `parse_test_list`.
+    fn parse_target_list(&mut self) -> Result<'a, Expression<'a>> {
+        let first = self.parse_star_or_expr()?;
+        if !self.at_operator(Operator::Sep) {
+            return Ok(first);
         }
 
+        let mut items = vec![first];
+        while self.eat_operator(Operator::Sep) && self.at_expression() {
+            items.push(self.parse_star_or_expr()?);
+        }
+        Ok(Expression::Tuple(items))
     }
 
-    fn parse_unary(&mut self) -> Result<'a, Expression<'a>> {
-        match *self.peek().expect(&format!("{}", line!())) {
-            Lexeme::Operator(op @ Operator::UnaryAdd) |
-            Lexeme::Operator(op @ Operator::UnaryNot) |
-            Lexeme::Operator(op @ Operator::UnarySub) => {
-                self.consume();
-                Ok(Expression::new_unary_op(self.parse_unary()?, op))
-            }
-            _ => self.parse_pow(),
+    /// Comma separated expressions, as in the targets of `del`.
+    fn parse_expr_list(&mut self) -> Result<'a, Vec<Expression<'a>>> {
+        let mut items = vec![self.parse_expr()?];
+        while self.eat_operator(Operator::Sep) && self.at_expression() {
+            items.push(self.parse_expr()?);
         }
+        Ok(items)
+    }
+
+    fn at_expression(&mut self) -> bool {
+        self.peek().map_or(false, starts_expression)
     }
 
-    fn parse_term(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_unary()?;
+    fn at_name(&mut self) -> bool {
         match self.peek() {
-            Some(&Lexeme::Operator(op @ Operator::Mul)) |
-            Some(&Lexeme::Operator(op @ Operator::Dec)) |
-            Some(&Lexeme::Operator(op @ Operator::Div)) |
-            Some(&Lexeme::Operator(op @ Operator::Rem)) |
-            Some(&Lexeme::Operator(op @ Operator::FloorDiv)) => {
-                Ok(Expression::new_binary_op(lhs, op, self.parse_unary()?))
-            }
-            _ => Ok(lhs),
+            Some(&Lexeme::Identifier(..)) => true,
+            _ => false,
         }
     }
 
-    fn parse_arith(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_term()?;
+    fn at_operator(&mut self, operator: Operator) -> bool {
         match self.peek() {
-            Some(&Lexeme::Operator(op @ Operator::Add)) |
-            Some(&Lexeme::Operator(op @ Operator::Sub)) => {
-                self.consume();
-                Ok(Expression::new_binary_op(lhs, op, self.parse_term()?))
-            }
-            _ => return Ok(lhs),
+            Some(&Lexeme::Operator(_, next)) => next == operator,
+            _ => false,
         }
     }
 
-    fn parse_shift(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_arith()?;
-        match self.peek() {
-            Some(&Lexeme::Operator(op @ Operator::Shl)) |
-            Some(&Lexeme::Operator(op @ Operator::Shr)) => {
-                self.consume();
-                Ok(Expression::new_binary_op(lhs, op, self.parse_arith()?))
-            }
-            _ => Ok(lhs),
+    /// Consumes the next lexeme if it's `operator`.
+    fn eat_operator(&mut self, operator: Operator) -> bool {
+        let found = self.at_operator(operator);
+        if found {
+            self.consume();
         }
+        found
     }
 
-    fn parse_and(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_shift()?;
+    fn at_keyword(&mut self, keyword: Keyword) -> bool {
         match self.peek() {
-            Some(&Lexeme::Operator(op @ Operator::And)) => {
-                self.consume();
-                Ok(Expression::new_binary_op(lhs, op, self.parse_shift()?))
-            }
-            _ => Ok(lhs),
+            Some(&Lexeme::Keyword(_, next)) => next == keyword,
+            _ => false,
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: Keyword) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.consume();
+        }
+        found
+    }
+
+    fn eat_delimiter(&mut self, delimiter: Delimiter) -> bool {
+        let found = self.at_delimiter(delimiter);
+        if found {
+            self.consume();
         }
+        found
     }
 
-    fn parse_xor(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_and()?;
+    fn at_delimiter(&mut self, delimiter: Delimiter) -> bool {
         match self.peek() {
-            Some(&Lexeme::Operator(op @ Operator::Xor)) => {
-                self.consume();
-                Ok(Expression::new_binary_op(lhs, op, self.parse_and()?))
-            }
-            _ => Ok(lhs),
+            Some(&Lexeme::Delimiter(_, next)) => next == delimiter,
+            _ => false,
         }
     }
 
-    fn parse_expr(&mut self) -> Result<'a, Expression<'a>> {
-        let lhs = self.parse_xor()?;
+    /// Consumes the next lexeme if it's `delimiter`, returning its span.
+    fn eat_closing(&mut self, delimiter: Delimiter) -> Option<Span> {
         match self.peek() {
-            Some(&Lexeme::Operator(op @ Operator::Or)) => {
-                self.consume();
-                Ok(Expression::new_binary_op(lhs, op, self.parse_xor()?))
-            }
-            _ => Ok(lhs)
+            Some(&Lexeme::Delimiter(_, next)) if next == delimiter => {}
+            _ => return None,
         }
+        self.next().map(|lexeme| lexeme.span())
     }
 
-    fn parse_dict(&mut self) -> Result<'a, Expression<'a>> {
-        unimplemented!()
+    fn expect_operator(&mut self, operator: Operator) -> Result<'a, Span> {
+        match get_or_eof!(self.next()) {
+            Lexeme::Operator(span, next) if next == operator => Ok(span),
+            lexeme => Err(UnexpectedToken(lexeme)),
+        }
     }
 
-    fn parse_list(&mut self) -> Result<'a, Expression<'a>> {
-        unimplemented!()
+    fn expect_keyword(&mut self, keyword: Keyword) -> Result<'a, Span> {
+        match get_or_eof!(self.next()) {
+            Lexeme::Keyword(span, next) if next == keyword => Ok(span),
+            lexeme => Err(UnexpectedToken(lexeme)),
+        }
     }
 
-}
+    fn expect_delimiter(&mut self, delimiter: Delimiter) -> Result<'a, Span> {
+        match get_or_eof!(self.next()) {
+            Lexeme::Delimiter(span, next) if next == delimiter => Ok(span),
+            lexeme => Err(UnexpectedToken(lexeme)),
+        }
+    }
 
-#[derive(Clone, Debug)]
-pub enum ParseError<'a> {
-    LexError(LexerError<'a>),
-    UnclosedDelimiter(usize),
-    Eof,
-}
+    /// Expressions compared by any number of comparison operators.
+    fn parse_comparison(&mut self) -> Result<'a, Expression<'a>> {
+        let left = self.parse_expr()?;
+        let mut ops = Vec::new();
+        let mut comparators = Vec::new();
+
+        loop {
+            let op = match self.peek() {
+                Some(&Lexeme::Operator(_, operator)) => match CompareOp::from_operator(operator) {
+                    Some(op) => op,
+                    None => break,
+                },
+                Some(&Lexeme::Keyword(_, Keyword::In)) => CompareOp::In,
+                Some(&Lexeme::Keyword(_, Keyword::Is)) => CompareOp::Is,
+                Some(&Lexeme::Keyword(_, Keyword::Not)) => match self.peek_nth(1) {
+                    Some(&Lexeme::Keyword(_, Keyword::In)) => CompareOp::NotIn,
+                    _ => break,
+                },
+                _ => break,
+            };
+            self.consume();
 
-impl<'a> From<LexerError<'a>> for ParseError<'a> {
-    fn from(from: LexerError<'a>) -> Self {
-        LexError(from)
+            // `not in` and `is not` are two keywords.
+            let op = match op {
+                CompareOp::NotIn => {
+                    self.consume();
+                    op
+                }
+                CompareOp::Is if self.eat_keyword(Keyword::Not) => CompareOp::IsNot,
+                op => op,
+            };
+            ops.push(op);
+            comparators.push(self.parse_expr()?);
+        }
+
+        if ops.is_empty() {
+            return Ok(left);
+        }
+
+        Ok(Expression::Compare(Box::new(Compare {
+            comparators: comparators,
+            left: left,
+            ops: ops,
+        })))
+    }
+
+    fn parse_not_test(&mut self) -> Result<'a, Expression<'a>> {
+        if self.eat_keyword(Keyword::Not) {
+            Ok(Expression::Not(Box::new(self.parse_not_test()?)))
+        } else {
+            self.parse_comparison()
+        }
+    }
+
+    fn parse_and_test(&mut self) -> Result<'a, Expression<'a>> {
+        let first = self.parse_not_test()?;
+        if !self.at_keyword(Keyword::And) {
+            return Ok(first);
+        }
+
+        let mut values = vec![first];
+        while self.eat_keyword(Keyword::And) {
+            values.push(self.parse_not_test()?);
+        }
+        Ok(Expression::BoolOp(BoolOperator::And, values))
+    }
+
+    fn parse_or_test(&mut self) -> Result<'a, Expression<'a>> {
+        let first = self.parse_and_test()?;
+        if !self.at_keyword(Keyword::Or) {
+            return Ok(first);
+        }
+
+        let mut values = vec![first];
+        while self.eat_keyword(Keyword::Or) {
+            values.push(self.parse_and_test()?);
+        }
+        Ok(Expression::BoolOp(BoolOperator::Or, values))
+    }
+
+    /// An expression, which can be a conditional expression or a lambda.
+    fn parse_test(&mut self) -> Result<'a, Expression<'a>> {
+        if self.at_keyword(Keyword::Lambda) {
+            return self.parse_lambda();
+        }
+
+        let body = self.parse_or_test()?;
+        if !self.eat_keyword(Keyword::If) {
+            return Ok(body);
+        }
+
+        let test = self.parse_or_test()?;
+        self.expect_keyword(Keyword::Else)?;
+        let orelse = self.parse_test()?;
+        Ok(Expression::Conditional(Box::new(body), Box::new(test), Box::new(orelse)))
+    }
+
+    fn parse_lambda(&mut self) -> Result<'a, Expression<'a>> {
+        self.expect_keyword(Keyword::Lambda)?;
+        let parameters = self.parse_parameters(false)?;
+        self.expect_operator(Operator::Colon)?;
+        let body = self.parse_test()?;
+        Ok(Expression::Lambda(Box::new(parameters), Box::new(body)))
+    }
+
+    /// An expression that can also be an assignment expression, `name :=
+    /// value`. Those are only allowed where this is used, like the condition
+    /// of an `if` or inside parentheses, and not as a statement of their own.
+    fn parse_named_expr(&mut self) -> Result<'a, Expression<'a>> {
+        let named = match self.peek_nth(1) {
+            Some(&Lexeme::Operator(_, Operator::Walrus)) => self.at_name(),
+            _ => false,
+        };
+        if !named {
+            return self.parse_test();
+        }
+
+        let name = self.parse_name()?;
+        self.consume();
+        let value = self.parse_test()?;
+        Ok(Expression::NamedExpr(name, Box::new(value)))
+    }
+
+    fn parse_await(&mut self) -> Result<'a, Expression<'a>> {
+        if let Some(&Lexeme::Keyword(_, Keyword::Await)) = self.peek() {
+            self.consume();
+            Ok(Expression::Await(Box::new(self.parse_primary()?)))
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    /// An atom followed by any number of calls, subscriptions and attribute
+    /// references.
+    fn parse_primary(&mut self) -> Result<'a, Expression<'a>> {
+        let mut primary = self.parse_atom()?;
+
+        loop {
+            let next = match self.peek() {
+                Some(&Lexeme::Delimiter(_, ParenOpen)) => {
+                    self.consume();
+                    let arg_list = self.parse_arg_list()?;
+                    self.expect_delimiter(ParenClose)?;
+                    Primary::new_call(primary, arg_list)
+                }
+
+                Some(&Lexeme::Delimiter(_, ListOpen)) => {
+                    self.consume();
+                    let sub_list = self.parse_sub_list()?;
+                    self.expect_delimiter(ListClose)?;
+                    Primary::new_subscription(primary, sub_list)
+                }
+
+                Some(&Lexeme::Operator(_, Operator::Access)) => {
+                    self.consume();
+                    let name = self.parse_name()?;
+                    Primary::new_attribute_ref(primary, name)
+                }
+
+                _ => return Ok(primary),
+            };
+            primary = Expression::Primary(next);
+        }
+    }
+
+    fn parse_sub_list(&mut self) -> Result<'a, Vec<Slice<'a>>> {
+        let mut sub_list = vec![self.parse_slice()?];
+        let mut comma = false;
+        while self.eat_operator(Operator::Sep) {
+            comma = true;
+            match self.peek() {
+                Some(&Lexeme::Delimiter(_, ListClose)) => break,
+                _ => sub_list.push(self.parse_slice()?),
+            }
+        }
+
+        // Without any ranges the indexes make a tuple, `a[1,]` is `a[(1,)]`.
+        if comma && sub_list.iter().all(|slice| match *slice {
+            Slice::Index(_) => true,
+            _ => false,
+        }) {
+            let items = sub_list.into_iter().map(|slice| match slice {
+                Slice::Index(index) => index,
+                _ => unreachable!(),
+            });
+            return Ok(vec![Slice::Index(Expression::Tuple(items.collect()))]);
+        }
+        Ok(sub_list)
+    }
+
+    fn parse_slice(&mut self) -> Result<'a, Slice<'a>> {
+        let lower = if self.at_operator(Operator::Colon) {
+            None
+        } else {
+            Some(self.parse_named_expr()?)
+        };
+
+        if !self.at_operator(Operator::Colon) {
+            // `lower` can only be missing if there's a colon.
+            return Ok(Slice::Index(lower.unwrap()));
+        }
+        if let Some(Expression::NamedExpr(..)) = lower {
+            // Only a single index can be an assignment expression.
+            return Err(UnexpectedToken(get_or_eof!(self.next())));
+        }
+        self.consume();
+
+        let upper = if self.at_expression() {
+            Some(self.parse_test()?)
+        } else {
+            None
+        };
+        let step = if self.eat_operator(Operator::Colon) && self.at_expression() {
+            Some(self.parse_test()?)
+        } else {
+            None
+        };
+
+        Ok(Slice::Range(lower, upper, step))
+    }
+
+    fn parse_atom(&mut self) -> Result<'a, Expression<'a>> {
+        let atom: Atom = match get_or_eof!(self.next()) {
+            Lexeme::Identifier(span, name) => {
+                Atom::Identifier(span, name)
+            }
+
+            lexeme @ Lexeme::Str(..) | lexeme @ Lexeme::FormattedStr(..) => {
+                self.parse_strings(lexeme)?
+            }
+
+            Lexeme::Bytes(span, mut bytes) => {
+                let mut end = span;
+                while let Some(&Lexeme::Bytes(..)) = self.peek() {
+                    if let Some(Lexeme::Bytes(next_span, next)) = self.next() {
+                        end = next_span;
+                        bytes.extend(next);
+                    }
+                }
+
+                Atom::Literal(Lexeme::Bytes(span.to(end), bytes))
+            }
+
+            token @ Lexeme::Float(..) |
+            token @ Lexeme::Imaginary(..) |
+            token @ Lexeme::Integer(..) |
+            token @ Lexeme::Keyword(_, Keyword::False) |
+            token @ Lexeme::Keyword(_, Keyword::None) |
+            token @ Lexeme::Keyword(_, Keyword::True) |
+            token @ Lexeme::Operator(_, Operator::Ellipsis) => {
+                Atom::Literal(token)
+            }
+
+            Lexeme::Delimiter(span, ParenOpen) => return self.parse_paren(span),
+            Lexeme::Delimiter(span, ListOpen) => return self.parse_list(span),
+            Lexeme::Delimiter(span, DictOpen) => return self.parse_dict(span),
+            other => return Err(UnexpectedToken(other)),
+        };
+
+        Ok(Expression::Primary(Primary::Atom(atom)))
+    }
+
+    /// Adjacent string literals are joined into one, which is an f-string if
+    /// any of its pieces are.
+    fn parse_strings(&mut self, first: Lexeme<'a>) -> Result<'a, Atom<'a>> {
+        let mut strings = vec![first];
+        loop {
+            match self.peek() {
+                Some(&Lexeme::Str(..)) | Some(&Lexeme::FormattedStr(..)) => {}
+                _ => break,
+            }
+            strings.extend(self.next());
+        }
+
+        let span = strings[0].span().to(strings[strings.len() - 1].span());
+        let formatted = strings.iter().any(|string| match *string {
+            Lexeme::FormattedStr(..) => true,
+            _ => false,
+        });
+
+        if !formatted {
+            let mut joined = String::new();
+            for string in strings {
+                if let Lexeme::Str(_, string) = string {
+                    joined.push_str(&string);
+                }
+            }
+            return Ok(Atom::Literal(Lexeme::Str(span, joined)));
+        }
+
+        let mut parts = Vec::new();
+        for string in strings {
+            match string {
+                Lexeme::Str(_, string) => push_literal(&mut parts, string),
+                Lexeme::FormattedStr(_, pieces) => {
+                    for part in self.parse_format_parts(pieces)? {
+                        match part {
+                            FormattedPart::Literal(string) => {
+                                push_literal(&mut parts, string)
+                            }
+                            field => parts.push(field),
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(Atom::Formatted(span, parts))
+    }
+
+    fn parse_format_parts(&mut self, parts: Vec<FormatPart<'a>>)
+        -> Result<'a, Vec<FormattedPart<'a>>>
+    {
+        let mut output = Vec::new();
+        for part in parts {
+            match part {
+                FormatPart::Literal(string) => push_literal(&mut output, string),
+                FormatPart::Field(field) => {
+                    let expression = self.parse_format_expression(&field)?;
+                    let format_spec = self.parse_format_parts(field.format_spec)?;
+                    output.push(FormattedPart::Field(Box::new(expression),
+                                                     field.conversion,
+                                                     format_spec));
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// Lexes and parses the expression of a replacement field on its own.
+    fn parse_format_expression(&mut self, field: &FormatField<'a>)
+        -> Result<'a, Expression<'a>>
+    {
+        let lexer = Lexer::in_brackets(field.source, field.expression.start);
+        let mut parser = Parser::new(lexer);
+        let expression = parser.parse_yield_or_test_list();
+        parser.check_lex_errors()?;
+        let expression = expression?;
+        parser.check_not_starred(&expression)?;
+
+        match parser.next() {
+            None | Some(Lexeme::Newline(_)) => Ok(expression),
+            Some(lexeme) => Err(UnexpectedToken(lexeme)),
+        }
+    }
+
+    /// An `or_expr` in the grammar: unary and binary operators over
+    /// primaries.
+    fn parse_expr(&mut self) -> Result<'a, Expression<'a>> {
+        self.parse_binary(0)
+    }
+
+    /// Parses by precedence climbing, taking binary operators for as long as
+    /// they bind at least as tightly as `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<'a, Expression<'a>> {
+        let mut lhs = self.parse_unary()?;
+
+        loop {
+            let (operator, precedence, associativity) = match self.peek() {
+                Some(&Lexeme::Operator(_, operator)) => match binary_operator(operator) {
+                    Some((precedence, associativity)) if precedence >= min_precedence => {
+                        (operator, precedence, associativity)
+                    }
+                    _ => break,
+                },
+                _ => break,
+            };
+            self.consume();
+
+            let next_precedence = match associativity {
+                Associativity::Left => precedence + 1,
+                Associativity::Right => precedence,
+            };
+            let rhs = self.parse_binary(next_precedence)?;
+            lhs = Expression::new_binary_op(lhs, operator, rhs);
+        }
+
+        Ok(lhs)
+    }
+
+    /// A prefix `+`, `-` or `~`. Its operand can't hold a looser binary
+    /// operator, but can hold `**`, so `-a ** b` is `-(a ** b)`.
+    fn parse_unary(&mut self) -> Result<'a, Expression<'a>> {
+        let operator = match self.peek() {
+            Some(&Lexeme::Operator(_, Operator::Add)) => Operator::UnaryAdd,
+            Some(&Lexeme::Operator(_, Operator::Not)) => Operator::UnaryNot,
+            Some(&Lexeme::Operator(_, Operator::Sub)) => Operator::UnarySub,
+            _ => return self.parse_await(),
+        };
+        self.consume();
+
+        let operand = self.parse_binary(UNARY_PRECEDENCE)?;
+        Ok(Expression::new_unary_op(operand, operator))
+    }
+
+    /// What follows a `(`: a parenthesized expression, a tuple or a generator
+    /// expression.
+    fn parse_paren(&mut self, open: Span) -> Result<'a, Expression<'a>> {
+        if self.eat_delimiter(ParenClose) {
+            return Ok(Expression::Tuple(Vec::new()));
+        }
+
+        if self.at_keyword(Keyword::Yield) {
+            let expression = self.parse_yield()?;
+            self.expect_delimiter(ParenClose)?;
+            return Ok(expression);
+        }
+
+        let first = self.parse_star_or_named()?;
+        if self.at_comp_for() {
+            let clauses = self.parse_comp_for(&first)?;
+            let comprehension = Comprehension::Generator(first, clauses);
+            check_comprehension(&comprehension)?;
+            let close = self.expect_delimiter(ParenClose)?;
+            return Ok(new_atom(Atom::Comprehension(open.to(close), Box::new(comprehension))));
+        }
+
+        if !self.at_operator(Operator::Sep) {
+            self.check_not_starred(&first)?;
+            self.expect_delimiter(ParenClose)?;
+            return Ok(first);
+        }
+
+        let items = self.parse_display_items(first, ParenClose)?;
+        self.expect_delimiter(ParenClose)?;
+        Ok(Expression::Tuple(items))
+    }
+
+    /// What follows a `[`: a list display or comprehension.
+    fn parse_list(&mut self, open: Span) -> Result<'a, Expression<'a>> {
+        if let Some(close) = self.eat_closing(ListClose) {
+            return Ok(new_atom(Atom::List(open.to(close), Vec::new())));
+        }
+
+        let first = self.parse_star_or_named()?;
+        if self.at_comp_for() {
+            let clauses = self.parse_comp_for(&first)?;
+            let comprehension = Comprehension::List(first, clauses);
+            check_comprehension(&comprehension)?;
+            let close = self.expect_delimiter(ListClose)?;
+            return Ok(new_atom(Atom::Comprehension(open.to(close), Box::new(comprehension))));
+        }
+
+        let items = self.parse_display_items(first, ListClose)?;
+        let close = self.expect_delimiter(ListClose)?;
+        Ok(new_atom(Atom::List(open.to(close), items)))
+    }
+
+    /// What follows a `{`: a dict or set display, or either comprehension.
+    /// It's a set if the first item isn't followed by a `:`.
+    fn parse_dict(&mut self, open: Span) -> Result<'a, Expression<'a>> {
+        if let Some(close) = self.eat_closing(DictClose) {
+            return Ok(new_atom(Atom::Dict(open.to(close), Vec::new())));
+        }
+
+        let first = if self.eat_operator(Operator::Pow) {
+            DictItem::DoubleStarred(self.parse_expr()?)
+        } else {
+            let key = self.parse_star_or_named()?;
+            let is_set = match key {
+                Expression::NamedExpr(..) | Expression::Starred(_) => true,
+                _ => !self.at_operator(Operator::Colon),
+            };
+
+            if is_set {
+                if self.at_comp_for() {
+                    let clauses = self.parse_comp_for(&key)?;
+                    let comprehension = Comprehension::Set(key, clauses);
+                    check_comprehension(&comprehension)?;
+                    let close = self.expect_delimiter(DictClose)?;
+                    return Ok(new_atom(Atom::Comprehension(open.to(close),
+                                                           Box::new(comprehension))));
+                }
+                let items = self.parse_display_items(key, DictClose)?;
+                let close = self.expect_delimiter(DictClose)?;
+                return Ok(new_atom(Atom::Set(open.to(close), items)));
+            }
+
+            self.consume();
+            let value = self.parse_test()?;
+            if self.at_comp_for() {
+                let clauses = self.parse_comp_for(&key)?;
+                let comprehension = Comprehension::Dict(key, value, clauses);
+                check_comprehension(&comprehension)?;
+                let close = self.expect_delimiter(DictClose)?;
+                return Ok(new_atom(Atom::Comprehension(open.to(close), Box::new(comprehension))));
+            }
+            DictItem::Pair(key, value)
+        };
+
+        let mut items = vec![first];
+        while self.eat_operator(Operator::Sep) && !self.at_delimiter(DictClose) {
+            if self.eat_operator(Operator::Pow) {
+                items.push(DictItem::DoubleStarred(self.parse_expr()?));
+            } else {
+                let key = self.parse_test()?;
+                self.expect_operator(Operator::Colon)?;
+                items.push(DictItem::Pair(key, self.parse_test()?));
+            }
+        }
+
+        let close = self.expect_delimiter(DictClose)?;
+        Ok(new_atom(Atom::Dict(open.to(close), items)))
+    }
+
+    /// The rest of the comma separated items of a display after `first`, up
+    /// to the `close` delimiter, which is left for the caller.
+    fn parse_display_items(&mut self, first: Expression<'a>, close: Delimiter)
+        -> Result<'a, Vec<Expression<'a>>>
+    {
+        let mut items = vec![first];
+        while self.eat_operator(Operator::Sep) && !self.at_delimiter(close) {
+            items.push(self.parse_star_or_named()?);
+        }
+        Ok(items)
+    }
+
+    /// The `for` clauses of a comprehension, each with its `if` conditions.
+    fn parse_comp_for(&mut self, element: &Expression<'a>)
+        -> Result<'a, Vec<ComprehensionFor<'a>>>
+    {
+        if let Expression::Starred(_) = *element {
+            return Err(UnexpectedToken(get_or_eof!(self.next())));
+        }
+
+        let mut clauses = Vec::new();
+        while self.at_comp_for() {
+            let (span, is_async) = match self.next() {
+                Some(Lexeme::Keyword(span, keyword)) => (span, keyword == Keyword::Async),
+                _ => unreachable!(),
+            };
+            if is_async {
+                self.consume();
+            }
+
+            let target = self.parse_target_list()?;
+            check_target(&target, false)?;
+            self.expect_keyword(Keyword::In)?;
+            let iterable = self.parse_or_test()?;
+            let mut conditions = Vec::new();
+            while self.eat_keyword(Keyword::If) {
+                conditions.push(self.parse_or_test()?);
+            }
+
+            clauses.push(ComprehensionFor {
+                conditions: conditions,
+                is_async: is_async,
+                iterable: iterable,
+                span: span,
+                target: target,
+            });
+        }
+        Ok(clauses)
+    }
+
+    fn at_comp_for(&mut self) -> bool {
+        match self.peek() {
+            Some(&Lexeme::Keyword(_, Keyword::For)) => true,
+            Some(&Lexeme::Keyword(_, Keyword::Async)) => match self.peek_nth(1) {
+                Some(&Lexeme::Keyword(_, Keyword::For)) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// `*` followed by the expression being unpacked.
+    fn parse_starred(&mut self) -> Result<'a, Expression<'a>> {
+        self.expect_operator(Operator::Mul)?;
+        Ok(Expression::Starred(Box::new(self.parse_expr()?)))
+    }
+
+    fn parse_star_or_expr(&mut self) -> Result<'a, Expression<'a>> {
+        if self.at_operator(Operator::Mul) {
+            self.parse_starred()
+        } else {
+            self.parse_expr()
+        }
+    }
+
+    fn parse_star_or_test(&mut self) -> Result<'a, Expression<'a>> {
+        if self.at_operator(Operator::Mul) {
+            self.parse_starred()
+        } else {
+            self.parse_test()
+        }
+    }
+
+    fn parse_star_or_named(&mut self) -> Result<'a, Expression<'a>> {
+        if self.at_operator(Operator::Mul) {
+            self.parse_starred()
+        } else {
+            self.parse_named_expr()
+        }
     }
+
 }
 
-impl<'a> ops::Deref for Parser<'a> {
-    type Target = MultiPeek<IntoIter<Lexeme<'a>>>;
-    fn deref(&self) -> &Self::Target {
-        &self.iter
+/// Checks the assignment expressions in a comprehension against the rules
+/// of PEP 572. They bind in the enclosing scope, so they can't assign to the
+/// comprehension's iteration variables, and can't be in an iterable.
+fn check_comprehension<'a>(comprehension: &Comprehension<'a>) -> Result<'a, ()> {
+    let mut targets = Vec::new();
+    let mut named = Vec::new();
+    for clause in comprehension.clauses() {
+        target_names(&clause.target, &mut targets);
+
+        let mut in_iterable = Vec::new();
+        named_expressions(&clause.iterable, &mut in_iterable);
+        if let Some(name) = in_iterable.into_iter().next() {
+            return Err(NamedExprInIterable(name.clone()));
+        }
+
+        for condition in &clause.conditions {
+            named_expressions(condition, &mut named);
+        }
+    }
+    for element in comprehension.elements() {
+        named_expressions(element, &mut named);
+    }
+
+    match named.into_iter().find(|name| targets.contains(&&*name.name)) {
+        Some(name) => Err(RebindsIterationVariable(name.clone())),
+        None => Ok(()),
     }
 }
 
-impl<'a> ops::DerefMut for Parser<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.iter
+/// Checks that `target` can be assigned to: a name, attribute or
+/// subscription, or a tuple or list of targets with at most one of them
+/// starred. `starred` is set for the items of a tuple or list.
+fn check_target<'a>(target: &Expression<'a>, starred: bool) -> Result<'a, ()> {
+    match *target {
+        Expression::Primary(Primary::Atom(Atom::Identifier(..))) |
+        Expression::Primary(Primary::AttributeRef(..)) |
+        Expression::Primary(Primary::Subscription(..)) => Ok(()),
+        Expression::Primary(Primary::Atom(Atom::List(_, ref items))) |
+        Expression::Tuple(ref items) => {
+            let mut stars = items.iter().filter(|item| match **item {
+                Expression::Starred(_) => true,
+                _ => false,
+            });
+            if let Some(second) = stars.nth(1) {
+                return Err(InvalidTarget(second.clone()));
+            }
+            for item in items {
+                check_target(item, true)?;
+            }
+            Ok(())
+        }
+        Expression::Starred(ref item) if starred => check_target(item, false),
+        _ => Err(InvalidTarget(target.clone())),
     }
 }
 
-impl<'a> fmt::Debug for Parser<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut iter = self.output.iter();
-        while let Some(ref ast) = iter.next() {
-            write!(f, "{:?}\n", ast)?;
+/// Checks a target of `del`, which is like an assignment target without
+/// starred items.
+fn check_delete_target<'a>(target: &Expression<'a>) -> Result<'a, ()> {
+    match *target {
+        Expression::Primary(Primary::Atom(Atom::Identifier(..))) |
+        Expression::Primary(Primary::AttributeRef(..)) |
+        Expression::Primary(Primary::Subscription(..)) => Ok(()),
+        Expression::Primary(Primary::Atom(Atom::List(_, ref items))) |
+        Expression::Tuple(ref items) => {
+            for item in items {
+                check_delete_target(item)?;
+            }
+            Ok(())
+        }
+        _ => Err(InvalidTarget(target.clone())),
+    }
+}
+
+/// Checks the target of an augmented or annotated assignment, which can't
+/// be a tuple or list.
+fn check_single_target<'a>(target: &Expression<'a>) -> Result<'a, ()> {
+    match *target {
+        Expression::Primary(Primary::Atom(Atom::Identifier(..))) |
+        Expression::Primary(Primary::AttributeRef(..)) |
+        Expression::Primary(Primary::Subscription(..)) => Ok(()),
+        _ => Err(InvalidTarget(target.clone())),
+    }
+}
+
+/// The names a target list assigns to.
+fn target_names<'a, 'b>(target: &'b Expression<'a>, names: &mut Vec<&'b str>) {
+    match *target {
+        Expression::Primary(Primary::Atom(Atom::Identifier(_, ref name))) => names.push(name),
+        Expression::Primary(Primary::Atom(Atom::List(_, ref items))) |
+        Expression::Tuple(ref items) => {
+            for item in items {
+                target_names(item, names);
+            }
+        }
+        Expression::Starred(ref item) => target_names(item, names),
+        _ => {}
+    }
+}
+
+/// The names assigned by the assignment expressions in `expression`, not
+/// counting those in the body of a lambda, which has its own scope.
+fn named_expressions<'a, 'b>(expression: &'b Expression<'a>, names: &mut Vec<&'b Name<'a>>) {
+    match *expression {
+        Expression::Await(ref operand) |
+        Expression::Not(ref operand) |
+        Expression::Starred(ref operand) => named_expressions(operand, names),
+        Expression::BoolOp(_, ref values) |
+        Expression::Tuple(ref values) => {
+            for value in values {
+                named_expressions(value, names);
+            }
+        }
+        Expression::Compare(ref compare) => {
+            named_expressions(&compare.left, names);
+            for comparator in &compare.comparators {
+                named_expressions(comparator, names);
+            }
+        }
+        Expression::Conditional(ref body, ref test, ref orelse) => {
+            named_expressions(body, names);
+            named_expressions(test, names);
+            named_expressions(orelse, names);
+        }
+        Expression::Lambda(ref parameters, _) => {
+            let all = parameters.positional_only.iter()
+                                .chain(&parameters.positional)
+                                .chain(&parameters.keyword_only);
+            for default in all.filter_map(|parameter| parameter.default.as_ref()) {
+                named_expressions(default, names);
+            }
+        }
+        Expression::NamedExpr(ref name, ref value) => {
+            names.push(name);
+            named_expressions(value, names);
+        }
+        Expression::Operation(ref lhs, _, ref rhs) => {
+            named_expressions(lhs, names);
+            if let Some(ref rhs) = *rhs {
+                named_expressions(rhs, names);
+            }
+        }
+        Expression::Primary(ref primary) => primary_named_expressions(primary, names),
+    }
+}
+
+fn primary_named_expressions<'a, 'b>(primary: &'b Primary<'a>, names: &mut Vec<&'b Name<'a>>) {
+    match *primary {
+        Primary::AttributeRef(ref object, _) => named_expressions(object, names),
+        Primary::Call(ref callee, ref arguments) => {
+            named_expressions(callee, names);
+            for argument in arguments {
+                match *argument {
+                    Argument::DoubleStarred(ref value) |
+                    Argument::Keyword(_, ref value) |
+                    Argument::Positional(ref value) |
+                    Argument::Starred(ref value) => named_expressions(value, names),
+                }
+            }
+        }
+        Primary::Subscription(ref object, ref slices) => {
+            named_expressions(object, names);
+            for slice in slices {
+                match *slice {
+                    Slice::Index(ref index) => named_expressions(index, names),
+                    Slice::Range(ref lower, ref upper, ref step) => {
+                        for bound in lower.iter().chain(upper).chain(step) {
+                            named_expressions(bound, names);
+                        }
+                    }
+                }
+            }
+        }
+        Primary::Atom(ref atom) => match *atom {
+            Atom::Comprehension(_, ref comprehension) => {
+                for element in comprehension.elements() {
+                    named_expressions(element, names);
+                }
+                for clause in comprehension.clauses() {
+                    named_expressions(&clause.iterable, names);
+                    for condition in &clause.conditions {
+                        named_expressions(condition, names);
+                    }
+                }
+            }
+            Atom::Dict(_, ref items) => {
+                for item in items {
+                    match *item {
+                        DictItem::DoubleStarred(ref value) => named_expressions(value, names),
+                        DictItem::Pair(ref key, ref value) => {
+                            named_expressions(key, names);
+                            named_expressions(value, names);
+                        }
+                    }
+                }
+            }
+            Atom::Formatted(_, ref parts) => formatted_named_expressions(parts, names),
+            Atom::List(_, ref items) |
+            Atom::Set(_, ref items) => {
+                for item in items {
+                    named_expressions(item, names);
+                }
+            }
+            Atom::Yield(Some(ref value)) |
+            Atom::YieldFrom(ref value) => named_expressions(value, names),
+            Atom::Identifier(..) | Atom::Literal(_) | Atom::Yield(None) => {}
+        },
+    }
+}
+
+fn formatted_named_expressions<'a, 'b>(parts: &'b [FormattedPart<'a>],
+                                       names: &mut Vec<&'b Name<'a>>) {
+    for part in parts {
+        if let FormattedPart::Field(ref expression, _, ref format_spec) = *part {
+            named_expressions(expression, names);
+            formatted_named_expressions(format_spec, names);
+        }
+    }
+}
+
+fn new_atom<'a>(atom: Atom<'a>) -> Expression<'a> {
+    Expression::Primary(Primary::Atom(atom))
+}
+
+/// Whether `lexeme` can be the first of an expression.
+fn starts_expression(lexeme: &Lexeme) -> bool {
+    match *lexeme {
+        Lexeme::Bytes(..) |
+        Lexeme::Float(..) |
+        Lexeme::FormattedStr(..) |
+        Lexeme::Identifier(..) |
+        Lexeme::Imaginary(..) |
+        Lexeme::Integer(..) |
+        Lexeme::Str(..) => true,
+        Lexeme::Delimiter(_, delimiter) => {
+            delimiter == DictOpen || delimiter == ListOpen || delimiter == ParenOpen
+        }
+        Lexeme::Keyword(_, keyword) => match keyword {
+            Keyword::Await | Keyword::False | Keyword::Lambda | Keyword::None |
+            Keyword::Not | Keyword::True | Keyword::Yield => true,
+            _ => false,
+        },
+        Lexeme::Operator(_, operator) => match operator {
+            Operator::Add | Operator::Ellipsis | Operator::Mul | Operator::Not |
+            Operator::Sub => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Adds literal text to the end of an f-string, joining it onto any literal
+/// text already there.
+fn push_literal<'a>(parts: &mut Vec<FormattedPart<'a>>, string: String) {
+    if let Some(&mut FormattedPart::Literal(ref mut last)) = parts.last_mut() {
+        last.push_str(&string);
+        return;
+    }
+    parts.push(FormattedPart::Literal(string));
+}
+
+#[derive(Clone, Debug)]
+pub enum ParseError<'a> {
+    LexError(LexerError<'a>),
+    // The lexer reports unclosed delimiters, and the parser stops at the lex
+    // error first, so this isn't constructed.
+    #[allow(dead_code)]
+    UnclosedDelimiter(usize),
+    UnexpectedToken(Lexeme<'a>),
+    /// An expression that can't be assigned to.
+    InvalidTarget(Expression<'a>),
+    /// An assignment expression in a comprehension that assigns to one of its
+    /// iteration variables.
+    RebindsIterationVariable(Name<'a>),
+    /// An assignment expression in the iterable of a comprehension.
+    NamedExprInIterable(Name<'a>),
+    Eof,
+}
+
+impl<'a> From<LexerError<'a>> for ParseError<'a> {
+    fn from(from: LexerError<'a>) -> Self {
+        LexError(from)
+    }
+}
+
+/// Lookahead over the lexer's output. Lexemes are only pulled from the lexer
+/// when they're needed, and lexer errors are set aside in `errors` so that
+/// parsing can carry on over the rest.
+pub struct Tokens<'a> {
+    buffer: VecDeque<Lexeme<'a>>,
+    errors: Vec<LexerError<'a>>,
+    lexer: Lexer<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(lexer: Lexer<'a>) -> Self {
+        Tokens {
+            buffer: VecDeque::new(),
+            errors: Vec::new(),
+            lexer: lexer,
+        }
+    }
+
+    /// Pulls from the lexer until the buffer holds more than `index`
+    /// lexemes, returning false if it runs out first.
+    fn fill(&mut self, index: usize) -> bool {
+        while self.buffer.len() <= index {
+            match self.lexer.next() {
+                Some(Ok(lexeme)) => self.buffer.push_back(lexeme),
+                Some(Err(error)) => self.errors.push(error),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// The next lexeme, without consuming it.
+    pub fn peek(&mut self) -> Option<&Lexeme<'a>> {
+        self.peek_nth(0)
+    }
+
+    /// The lexeme `n` places after the next one, without consuming anything.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Lexeme<'a>> {
+        if self.fill(n) {
+            self.buffer.get(n)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Lexeme<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fill(0) {
+            self.buffer.pop_front()
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ops::Deref for Parser<'a> {
+    type Target = Tokens<'a>;
+    fn deref(&self) -> &Self::Target {
+        &self.iter
+    }
+}
+
+impl<'a> ops::DerefMut for Parser<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.iter
+    }
+}
+
+impl<'a> fmt::Debug for Parser<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut iter = self.output.iter();
+        while let Some(ref ast) = iter.next() {
+            write!(f, "{:?}\n", ast)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexeme::Conversion;
+
+    fn parse_value(source: &str) -> Expression {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse().unwrap();
+
+        match parser.output().pop() {
+            Some(Ast::Statement(Statement::Assignment(_, value))) => value,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn parse_statements(source: &str) -> Vec<Statement> {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse().unwrap();
+
+        parser.output().into_iter().map(|ast| match ast {
+            Ast::Statement(statement) => statement,
+            other => panic!("{:?}", other),
+        }).collect()
+    }
+
+    fn names(names: &[Name]) -> Vec<String> {
+        names.iter().map(|name| name.name.to_string()).collect()
+    }
+
+    #[test]
+    fn simple_statements() {
+        let statements = parse_statements("pass; break; continue;\n\
+                                           del a, b\n\
+                                           return\n\
+                                           return a, b,\n\
+                                           raise\n\
+                                           raise a from b\n\
+                                           global a, b\n\
+                                           nonlocal c\n\
+                                           assert a, 'message'\n\
+                                           a\n");
+        assert_eq!(statements.len(), 12);
+
+        match (&statements[0], &statements[1], &statements[2]) {
+            (&Statement::Pass(pass), &Statement::Break(_), &Statement::Continue(_)) => {
+                assert_eq!(pass, ::span::Span::new(0, 4));
+            }
+            other => panic!("{:?}", other),
+        }
+        match statements[3] {
+            Statement::Delete(_, ref targets) => assert_eq!(targets.len(), 2),
+            ref other => panic!("{:?}", other),
+        }
+        match (&statements[4], &statements[5]) {
+            (&Statement::Return(_, None),
+             &Statement::Return(_, Some(Expression::Tuple(ref items)))) => {
+                assert_eq!(items.len(), 2)
+            }
+            other => panic!("{:?}", other),
+        }
+        match (&statements[6], &statements[7]) {
+            (&Statement::Raise(_, None, None), &Statement::Raise(_, Some(_), Some(_))) => {}
+            other => panic!("{:?}", other),
+        }
+        match (&statements[8], &statements[9]) {
+            (&Statement::Global(_, ref global), &Statement::NonLocal(_, ref nonlocal)) => {
+                assert_eq!(names(global), ["a", "b"]);
+                assert_eq!(names(nonlocal), ["c"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        match (&statements[10], &statements[11]) {
+            (&Statement::Assert(_, _, Some(_)), &Statement::Expression(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn imports() {
+        let statements = parse_statements("import a.b as c, d\n\
+                                           from ... import e\n\
+                                           from .f.g import (h as i, j,)\n\
+                                           from k import *\n");
+
+        match statements[0] {
+            Statement::Import(_, ref aliases) => {
+                assert_eq!(names(&aliases[0].path), ["a", "b"]);
+                assert_eq!(aliases[0].alias.as_ref().unwrap().name, "c");
+                assert_eq!(names(&aliases[1].path), ["d"]);
+                assert!(aliases[1].alias.is_none());
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match statements[1] {
+            Statement::ImportFrom(_, ref from) => {
+                assert_eq!(from.level, 3);
+                assert!(from.module.is_empty());
+                assert_eq!(names(&from.names.as_ref().unwrap()[0].path), ["e"]);
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match statements[2] {
+            Statement::ImportFrom(_, ref from) => {
+                assert_eq!(from.level, 1);
+                assert_eq!(names(&from.module), ["f", "g"]);
+                let imported = from.names.as_ref().unwrap();
+                assert_eq!(imported.len(), 2);
+                assert_eq!(imported[0].alias.as_ref().unwrap().name, "i");
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match statements[3] {
+            Statement::ImportFrom(_, ImportFrom { names: None, .. }) => {}
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    fn compound<'a, 'b>(statement: &'b Statement<'a>) -> &'b CompoundStatement<'a> {
+        match *statement {
+            Statement::Compound(ref compound) => compound,
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn compound_statements() {
+        let statements = parse_statements("if a:\n\
+                                           \x20   pass\n\
+                                           elif b: pass\n\
+                                           elif c:\n\
+                                           \x20   while d:\n\
+                                           \x20       break\n\
+                                           \x20   else: pass\n\
+                                           else:\n\
+                                           \x20   for e, f in g:\n\
+                                           \x20       continue\n\
+                                           \x20       pass\n\
+                                           with h as i, j: pass\n\
+                                           with (k as l, m,):\n\
+                                           \x20   pass\n");
+        assert_eq!(statements.len(), 3);
+
+        match *compound(&statements[0]) {
+            CompoundStatement::If(ref if_) => {
+                assert_eq!(if_.body.len(), 1);
+                assert_eq!(if_.elif_cases.len(), 2);
+                match *compound(&if_.elif_cases[1].1[0]) {
+                    CompoundStatement::While(ref while_) => {
+                        assert!(while_.else_case.is_some())
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+                let else_case = if_.else_case.as_ref().unwrap();
+                match *compound(&else_case[0]) {
+                    CompoundStatement::For(ref for_) => {
+                        assert_eq!(for_.body.len(), 2);
+                        assert!(for_.else_case.is_none());
+                        match for_.target {
+                            Expression::Tuple(ref items) => assert_eq!(items.len(), 2),
+                            ref other => panic!("{:?}", other),
+                        }
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match (compound(&statements[1]), compound(&statements[2])) {
+            (&CompoundStatement::With(ref first), &CompoundStatement::With(ref second)) => {
+                assert_eq!(first.items.len(), 2);
+                assert!(first.items[0].target.is_some());
+                assert!(first.items[1].target.is_none());
+                assert_eq!(second.items.len(), 2);
+                assert!(second.items[0].target.is_some());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn try_statements() {
+        let statements = parse_statements("try:\n\
+                                           \x20   pass\n\
+                                           except a as b:\n\
+                                           \x20   pass\n\
+                                           except:\n\
+                                           \x20   pass\n\
+                                           else:\n\
+                                           \x20   pass\n\
+                                           finally:\n\
+                                           \x20   pass\n\
+                                           try: pass\n\
+                                           except* a: pass\n\
+                                           try: pass\n\
+                                           finally: pass\n");
+
+        match *compound(&statements[0]) {
+            CompoundStatement::Try(ref try_) => {
+                assert!(!try_.star);
+                assert_eq!(try_.handlers.len(), 2);
+                assert_eq!(try_.handlers[0].name.as_ref().unwrap().name, "b");
+                assert!(try_.handlers[1].expression.is_none());
+                assert!(try_.else_case.is_some());
+                assert!(try_.finally.is_some());
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match *compound(&statements[1]) {
+            CompoundStatement::Try(ref try_) => assert!(try_.star),
+            ref other => panic!("{:?}", other),
+        }
+        match *compound(&statements[2]) {
+            CompoundStatement::Try(ref try_) => {
+                assert!(try_.handlers.is_empty());
+                assert!(try_.finally.is_some());
+            }
+            ref other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        match error("try: pass\nexcept a: pass\nexcept* b: pass\n") {
+            UnexpectedToken(Lexeme::Keyword(_, Keyword::Except)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("try: pass\nexcept: pass\nexcept a: pass\n") {
+            UnexpectedToken(Lexeme::Keyword(_, Keyword::Except)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("try: pass\nelse: pass\n") {
+            UnexpectedToken(Lexeme::Keyword(_, Keyword::Else)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("if a:\npass\n") {
+            UnexpectedToken(Lexeme::Keyword(_, Keyword::Pass)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    fn parameter_names(parameters: &[Parameter]) -> Vec<String> {
+        parameters.iter().map(|parameter| parameter.name.name.to_string()).collect()
+    }
+
+    #[test]
+    fn definitions() {
+        let statements = parse_statements("@property\n\
+                                           @a.b\n\
+                                           def f(a, b: int = 1, /, c=2, *args: str, d, e=3,\n\
+                                           \x20     **kwargs) -> None:\n\
+                                           \x20   return a\n\
+                                           def g(*, a): pass\n\
+                                           def h(): pass\n\
+                                           class A(B, *bases, metaclass=M, **options):\n\
+                                           \x20   def method(self): pass\n\
+                                           class C: pass\n");
+
+        match *compound(&statements[0]) {
+            CompoundStatement::FunctionDef(ref def) => {
+                assert_eq!(def.name.name, "f");
+                assert_eq!(def.decorators.len(), 2);
+                let parameters = &def.parameters;
+                assert_eq!(parameter_names(&parameters.positional_only), ["a", "b"]);
+                assert!(parameters.positional_only[1].annotation.is_some());
+                assert!(parameters.positional_only[1].default.is_some());
+                assert_eq!(parameter_names(&parameters.positional), ["c"]);
+                assert_eq!(parameters.varargs.as_ref().unwrap().name.name, "args");
+                assert!(parameters.varargs.as_ref().unwrap().annotation.is_some());
+                assert_eq!(parameter_names(&parameters.keyword_only), ["d", "e"]);
+                assert_eq!(parameters.kwargs.as_ref().unwrap().name.name, "kwargs");
+                assert!(def.returns.is_some());
+                assert_eq!(def.body.len(), 1);
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match (compound(&statements[1]), compound(&statements[2])) {
+            (&CompoundStatement::FunctionDef(ref g), &CompoundStatement::FunctionDef(ref h)) => {
+                assert!(g.parameters.varargs.is_none());
+                assert_eq!(parameter_names(&g.parameters.keyword_only), ["a"]);
+                assert!(h.parameters.positional.is_empty());
+                assert!(h.returns.is_none());
+            }
+            other => panic!("{:?}", other),
+        }
+        match *compound(&statements[3]) {
+            CompoundStatement::ClassDef(ref class) => {
+                assert_eq!(class.name.name, "A");
+                match &class.arguments[..] {
+                    &[Argument::Positional(_), Argument::Starred(_),
+                      Argument::Keyword(ref keyword, _), Argument::DoubleStarred(_)] => {
+                        assert_eq!(keyword.name, "metaclass")
+                    }
+                    other => panic!("{:?}", other),
+                }
+                match *compound(&class.body[0]) {
+                    CompoundStatement::FunctionDef(ref method) => {
+                        assert_eq!(method.name.name, "method")
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match *compound(&statements[4]) {
+            CompoundStatement::ClassDef(ref class) => assert!(class.arguments.is_empty()),
+            ref other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        for source in &["def f(a=1, b): pass\n", "def f(*): pass\n", "def f(*, **k): pass\n",
+                        "def f(/): pass\n", "def f(*a, /): pass\n", "def f(*a, *b): pass\n",
+                        "class A(b=1, C): pass\n", "@decorator\nx = 1\n"] {
+            match error(source) {
+                UnexpectedToken(_) => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    fn parse_expression(source: &str) -> Expression {
+        match parse_statements(source).pop() {
+            Some(Statement::Expression(expression)) => expression,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn calls() {
+        let arguments = match parse_expression("print('hi', *args, end='', **kwargs)\n") {
+            Expression::Primary(Primary::Call(_, arguments)) => arguments,
+            other => panic!("{:?}", other),
+        };
+        match &arguments[..] {
+            &[Argument::Positional(_), Argument::Starred(_),
+              Argument::Keyword(ref keyword, _), Argument::DoubleStarred(_)] => {
+                assert_eq!(keyword.name, "end")
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // Calls, subscriptions and attributes apply left to right.
+        match parse_expression("a.b(c)[d].e()\n") {
+            Expression::Primary(Primary::Call(ref callee, ref arguments)) => {
+                assert!(arguments.is_empty());
+                match **callee {
+                    Expression::Primary(Primary::AttributeRef(ref object, ref name)) => {
+                        assert_eq!(name.name, "e");
+                        match **object {
+                            Expression::Primary(Primary::Subscription(..)) => {}
+                            ref other => panic!("{:?}", other),
+                        }
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        for source in &["f(a=1, b)\n", "f(**a, *b)\n", "f(a\n", "f(,)\n"] {
+            match error(source) {
+                UnexpectedToken(_) => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn slices() {
+        let slices = match parse_expression("a[1:2:3, ..., :, ::2, b:, 4]\n") {
+            Expression::Primary(Primary::Subscription(_, slices)) => slices,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(slices.len(), 6);
+        match &slices[..] {
+            &[Slice::Range(Some(_), Some(_), Some(_)),
+              Slice::Index(Expression::Primary(Primary::Atom(Atom::Literal(
+                  Lexeme::Operator(_, Operator::Ellipsis))))),
+              Slice::Range(None, None, None),
+              Slice::Range(None, None, Some(_)),
+              Slice::Range(Some(_), None, None),
+              Slice::Index(_)] => {}
+            other => panic!("{:?}", other),
+        }
+
+        for &(source, length) in &[("a[1]\n", None), ("a[1,]\n", Some(1)), ("a[1, 2]\n", Some(2))] {
+            match parse_expression(source) {
+                Expression::Primary(Primary::Subscription(_, slices)) => match &slices[..] {
+                    &[Slice::Index(Expression::Tuple(ref items))] => {
+                        assert_eq!(Some(items.len()), length)
+                    }
+                    &[Slice::Index(_)] => assert_eq!(length, None),
+                    other => panic!("{:?}", other),
+                },
+                other => panic!("{:?}", other),
+            }
+        }
+
+        match Parser::new(Lexer::new("a[]\n")).parse().unwrap_err() {
+            UnexpectedToken(Lexeme::Delimiter(_, ListClose)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn displays() {
+        match parse_expression("(a)\n") {
+            Expression::Primary(Primary::Atom(Atom::Identifier(_, ref name))) => {
+                assert_eq!(name, "a")
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("()\n") {
+            Expression::Tuple(ref items) => assert!(items.is_empty()),
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("(a,)\n") {
+            Expression::Tuple(ref items) => assert_eq!(items.len(), 1),
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("(a, *b, c)\n") {
+            Expression::Tuple(ref items) => match items[1] {
+                Expression::Starred(_) => {}
+                ref other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("[a, [b], *c,]\n") {
+            Expression::Primary(Primary::Atom(Atom::List(span, ref items))) => {
+                assert_eq!(span, ::span::Span::new(0, 13));
+                assert_eq!(items.len(), 3);
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("[]\n") {
+            Expression::Primary(Primary::Atom(Atom::List(_, ref items))) => {
+                assert!(items.is_empty())
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("{a, *b}\n") {
+            Expression::Primary(Primary::Atom(Atom::Set(_, ref items))) => {
+                assert_eq!(items.len(), 2)
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("{}\n") {
+            Expression::Primary(Primary::Atom(Atom::Dict(_, ref items))) => {
+                assert!(items.is_empty())
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("{'a': 1, **b, c: d,}\n") {
+            Expression::Primary(Primary::Atom(Atom::Dict(_, ref items))) => match &items[..] {
+                &[DictItem::Pair(..), DictItem::DoubleStarred(_), DictItem::Pair(..)] => {}
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        for source in &["(*a)\n", "[a b]\n", "{a: 1, b}\n", "{**a for a in b}\n", "(a\n"] {
+            match error(source) {
+                UnexpectedToken(_) | Eof => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    fn comprehension<'a>(expression: Expression<'a>) -> Comprehension<'a> {
+        match expression {
+            Expression::Primary(Primary::Atom(Atom::Comprehension(_, comprehension))) => {
+                *comprehension
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn comprehensions() {
+        match comprehension(parse_expression("[x for x in a if x if not y for z, w in x]\n")) {
+            Comprehension::List(_, ref clauses) => {
+                assert_eq!(clauses.len(), 2);
+                assert_eq!(clauses[0].conditions.len(), 2);
+                assert!(!clauses[0].is_async);
+                match clauses[1].target {
+                    Expression::Tuple(ref items) => assert_eq!(items.len(), 2),
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+        match comprehension(parse_expression("{x for x in a}\n")) {
+            Comprehension::Set(..) => {}
+            other => panic!("{:?}", other),
+        }
+        match comprehension(parse_expression("{k: v async for k, v in a}\n")) {
+            Comprehension::Dict(_, _, ref clauses) => assert!(clauses[0].is_async),
+            other => panic!("{:?}", other),
+        }
+        match comprehension(parse_expression("(x for x in a)\n")) {
+            Comprehension::Generator(..) => {}
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("sum(x for x in a)\n") {
+            Expression::Primary(Primary::Call(_, ref arguments)) => match &arguments[..] {
+                &[Argument::Positional(ref argument)] => {
+                    match comprehension(argument.clone()) {
+                        Comprehension::Generator(..) => {}
+                        other => panic!("{:?}", other),
+                    }
+                }
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        let statements = parse_statements("async def f():\n\
+                                           \x20   async with a: pass\n");
+        match *compound(&statements[0]) {
+            CompoundStatement::FunctionDef(ref def) => {
+                assert!(def.is_async);
+                match *compound(&def.body[0]) {
+                    CompoundStatement::With(ref with) => assert!(with.is_async),
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            ref other => panic!("{:?}", other),
+        }
+
+        for source in &["f(a, x for x in b)\n", "f(x for x in b, a)\n", "[*a for a in b]\n",
+                        "async class A: pass\n"] {
+            match error(source) {
+                UnexpectedToken(_) => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    /// Writes out the operators in an expression with their operands in
+    /// brackets, to check how they group.
+    fn grouping(expression: &Expression) -> String {
+        match *expression {
+            Expression::Operation(ref lhs, operator, None) => {
+                format!("({:?} {})", operator, grouping(lhs))
+            }
+            Expression::Operation(ref lhs, operator, Some(ref rhs)) => {
+                format!("({} {:?} {})", grouping(lhs), operator, grouping(rhs))
+            }
+            Expression::Primary(Primary::Atom(Atom::Identifier(_, ref name))) => name.to_string(),
+            ref other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn binary_operators() {
+        let group = |source: &str| grouping(&parse_expression(&format!("{}\n", source)));
+
+        assert_eq!(group("a + b + c + d"), "(((a Add b) Add c) Add d)");
+        assert_eq!(group("a - b * c"), "(a Sub (b Mul c))");
+        assert_eq!(group("a * b // c % d @ e"),
+                   "((((a Mul b) FloorDiv c) Rem d) Dec e)");
+        assert_eq!(group("a | b ^ c & d << e + f"),
+                   "(a Or (b Xor (c And (d Shl (e Add f)))))");
+        assert_eq!(group("a >> b << c"), "((a Shr b) Shl c)");
+        assert_eq!(group("a ** b ** c"), "(a Pow (b Pow c))");
+        assert_eq!(group("-a ** b"), "(UnarySub (a Pow b))");
+        assert_eq!(group("a ** -b"), "(a Pow (UnarySub b))");
+        assert_eq!(group("-a * b"), "((UnarySub a) Mul b)");
+        assert_eq!(group("~-+a"), "(UnaryNot (UnarySub (UnaryAdd a)))");
+        assert_eq!(group("a-b"), "(a Sub b)");
+        assert_eq!(group("a+-b"), "(a Add (UnarySub b))");
+    }
+
+    #[test]
+    fn comparisons() {
+        match parse_expression("a < b <= c != d is not e not in f in g is h == i > j >= k\n") {
+            Expression::Compare(ref compare) => {
+                assert_eq!(compare.ops, [CompareOp::LessThan, CompareOp::LessThanEqual,
+                                         CompareOp::NotEquals, CompareOp::IsNot,
+                                         CompareOp::NotIn, CompareOp::In, CompareOp::Is,
+                                         CompareOp::Equals, CompareOp::MoreThan,
+                                         CompareOp::MoreThanEqual]);
+                assert_eq!(compare.comparators.len(), 10);
+                assert_eq!(grouping(&compare.left), "a");
+                assert_eq!(grouping(&compare.comparators[9]), "k");
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // Arithmetic binds tighter than comparisons.
+        match parse_expression("a + b < c\n") {
+            Expression::Compare(ref compare) => {
+                assert_eq!(grouping(&compare.left), "(a Add b)")
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match Parser::new(Lexer::new("a not b\n")).parse().unwrap_err() {
+            UnexpectedToken(Lexeme::Keyword(_, Keyword::Not)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn boolean_operators() {
+        match parse_expression("a or b and c and not d or e\n") {
+            Expression::BoolOp(BoolOperator::Or, ref values) => {
+                assert_eq!(values.len(), 3);
+                match values[1] {
+                    Expression::BoolOp(BoolOperator::And, ref values) => {
+                        assert_eq!(values.len(), 3);
+                        match values[2] {
+                            Expression::Not(ref operand) => assert_eq!(grouping(operand), "d"),
+                            ref other => panic!("{:?}", other),
+                        }
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match parse_expression("not a == b\n") {
+            Expression::Not(ref operand) => match **operand {
+                Expression::Compare(_) => {}
+                ref other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn conditionals_and_lambdas() {
+        match parse_expression("a if b else c if d else e\n") {
+            Expression::Conditional(ref body, ref test, ref orelse) => {
+                assert_eq!(grouping(body), "a");
+                assert_eq!(grouping(test), "b");
+                match **orelse {
+                    Expression::Conditional(..) => {}
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("a or b if c and d else e\n") {
+            Expression::Conditional(ref body, ref test, _) => {
+                match (&**body, &**test) {
+                    (&Expression::BoolOp(BoolOperator::Or, _),
+                     &Expression::BoolOp(BoolOperator::And, _)) => {}
+                    other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("lambda a, b=1, *c, d, **e: a if b else c\n") {
+            Expression::Lambda(ref parameters, ref body) => {
+                assert_eq!(parameter_names(&parameters.positional), ["a", "b"]);
+                assert_eq!(parameter_names(&parameters.keyword_only), ["d"]);
+                assert!(parameters.varargs.is_some() && parameters.kwargs.is_some());
+                match **body {
+                    Expression::Conditional(..) => {}
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse_expression("lambda: 0\n") {
+            Expression::Lambda(ref parameters, _) => assert!(parameters.positional.is_empty()),
+            other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        match error("a if b\n") {
+            UnexpectedToken(_) => {}
+            other => panic!("{:?}", other),
+        }
+        // Lambda parameters can't be annotated, so this is annotating a
+        // lambda.
+        match error("lambda a: int: a\n") {
+            InvalidTarget(Expression::Lambda(..)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn assignment_expressions() {
+        let statements = parse_statements("if (n := len(a)) > 10: pass\n\
+                                           while chunk := read(): pass\n\
+                                           f(x := 1)\n\
+                                           [y := f(x), y ** 2]\n\
+                                           [y for x in a if (y := f(x))]\n\
+                                           [lambda: (x := 1) for x in a]\n");
+        assert_eq!(statements.len(), 6);
+        match *compound(&statements[1]) {
+            CompoundStatement::While(ref while_) => match while_.expression {
+                Expression::NamedExpr(ref name, _) => assert_eq!(name.name, "chunk"),
+                ref other => panic!("{:?}", other),
+            },
+            ref other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        match error("x := 1\n") {
+            UnexpectedToken(Lexeme::Operator(_, Operator::Walrus)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("[i := 0 for i in a]\n") {
+            RebindsIterationVariable(ref name) => assert_eq!(name.name, "i"),
+            other => panic!("{:?}", other),
+        }
+        match error("[x for x, *y in a if (y := x)]\n") {
+            RebindsIterationVariable(ref name) => assert_eq!(name.name, "y"),
+            other => panic!("{:?}", other),
+        }
+        match error("[[(j := j) for i in a] for j in b]\n") {
+            RebindsIterationVariable(ref name) => assert_eq!(name.name, "j"),
+            other => panic!("{:?}", other),
+        }
+        match error("{x: y for x in (y := a)}\n") {
+            NamedExprInIterable(ref name) => assert_eq!(name.name, "y"),
+            other => panic!("{:?}", other),
+        }
+        match error("[x for x in a for z in f(y := x)]\n") {
+            NamedExprInIterable(ref name) => assert_eq!(name.name, "y"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        let statements = parse_statements("a = b = c\n\
+                                           a, *rest = [b, c] = d\n\
+                                           a.b[c] += 1\n\
+                                           a //= b\n\
+                                           a: int = 3\n\
+                                           a.b: List[int]\n\
+                                           (a, b), c = d\n");
+        match statements[0] {
+            Statement::Assignment(ref targets, ref value) => {
+                assert_eq!(targets.iter().map(grouping).collect::<Vec<_>>(), ["a", "b"]);
+                assert_eq!(grouping(value), "c");
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match statements[1] {
+            Statement::Assignment(ref targets, _) => match (&targets[0], &targets[1]) {
+                (&Expression::Tuple(ref items),
+                 &Expression::Primary(Primary::Atom(Atom::List(..)))) => {
+                    match items[1] {
+                        Expression::Starred(_) => {}
+                        ref other => panic!("{:?}", other),
+                    }
+                }
+                other => panic!("{:?}", other),
+            },
+            ref other => panic!("{:?}", other),
+        }
+        match (&statements[2], &statements[3]) {
+            (&Statement::AugAssign(Expression::Primary(Primary::Subscription(..)),
+                                   Operator::Add, _),
+             &Statement::AugAssign(_, Operator::FloorDiv, _)) => {}
+            other => panic!("{:?}", other),
+        }
+        match (&statements[4], &statements[5]) {
+            (&Statement::AnnAssign(_, _, Some(_)),
+             &Statement::AnnAssign(Expression::Primary(Primary::AttributeRef(..)), _, None)) => {}
+            other => panic!("{:?}", other),
+        }
+        match statements[6] {
+            Statement::Assignment(..) => {}
+            ref other => panic!("{:?}", other),
+        }
+
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+        for source in &["a = *b\n", "a = b = *c\n", "a += *b\n", "a: int = *b\n", "*a\n"] {
+            match error(source) {
+                UnexpectedToken(Lexeme::Newline(_)) => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+        for source in &["f() = 1\n", "a + b = 1\n", "1 = a\n", "a = f() = 1\n",
+                        "*a = b\n", "*a, *b = c\n", "a, b += 1\n", "[a]: int\n",
+                        "(a, f()) = b\n", "a if b else c = d\n", "for f() in a: pass\n",
+                        "[x for 1 in a]\n", "with a as b + c: pass\n", "del f()\n",
+                        "del a, (b, *c)\n"] {
+            match error(source) {
+                InvalidTarget(_) => {}
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+    }
+
+    #[test]
+    fn statement_errors() {
+        let error = |source| Parser::new(Lexer::new(source)).parse().unwrap_err();
+
+        match error("pass pass\n") {
+            UnexpectedToken(Lexeme::Keyword(_, Keyword::Pass)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("import a.\n") {
+            UnexpectedToken(Lexeme::Newline(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("from a import b,\n") {
+            UnexpectedToken(Lexeme::Newline(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match error("global") {
+            UnexpectedToken(Lexeme::Newline(_)) | Eof => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn formatted_string() {
+        let parts = match parse_value("x = f'a{{{b!r:>{width}}}}'\n") {
+            Expression::Primary(Primary::Atom(Atom::Formatted(_, parts))) => parts,
+            other => panic!("{:?}", other),
+        };
+
+        assert_eq!(parts.len(), 3);
+        match parts[0] {
+            FormattedPart::Literal(ref string) => assert_eq!(string, "a{"),
+            ref other => panic!("{:?}", other),
+        }
+        match parts[1] {
+            FormattedPart::Field(ref expression, conversion, ref spec) => {
+                match **expression {
+                    Expression::Primary(Primary::Atom(Atom::Identifier(span, ref name))) => {
+                        assert_eq!(name, "b");
+                        assert_eq!(span, ::span::Span::new(10, 11));
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+                assert_eq!(conversion, Some(Conversion::Repr));
+                assert_eq!(spec.len(), 2);
+            }
+            ref other => panic!("{:?}", other),
+        }
+        match parts[2] {
+            FormattedPart::Literal(ref string) => assert_eq!(string, "}"),
+            ref other => panic!("{:?}", other),
+        }
+
+        // Fields are lexed as if inside brackets.
+        for source in &["x = f'{ a }'\n", "x = f'{b:{ c }}'\n", "x = f'''{a +\n b}'''\n"] {
+            match parse_value(source) {
+                Expression::Primary(Primary::Atom(Atom::Formatted(_, parts))) => {
+                    assert_eq!(parts.len(), 1);
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+
+        // A self documenting field keeps its text and defaults to the repr.
+        match parse_value("x = f'{ a = }{b=:>4}{c!=d=}'\n") {
+            Expression::Primary(Primary::Atom(Atom::Formatted(_, parts))) => {
+                assert_eq!(parts.len(), 6);
+                match parts[0] {
+                    FormattedPart::Literal(ref string) => assert_eq!(string, " a = "),
+                    ref other => panic!("{:?}", other),
+                }
+                match parts[1] {
+                    FormattedPart::Field(_, conversion, _) => {
+                        assert_eq!(conversion, Some(Conversion::Repr));
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+                match parts[3] {
+                    FormattedPart::Field(_, conversion, ref spec) => {
+                        assert_eq!(conversion, None);
+                        assert_eq!(spec.len(), 1);
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+                match parts[4] {
+                    FormattedPart::Literal(ref string) => assert_eq!(string, "c!=d="),
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // A field can be a tuple or a yield, like the value of an assignment.
+        for &(source, length) in &[("x = f'{a, b}'\n", 2), ("x = f'{*a, b}'\n", 2),
+                                   ("x = f'{a,}'\n", 1)] {
+            match parse_value(source) {
+                Expression::Primary(Primary::Atom(Atom::Formatted(_, parts))) => match parts[0] {
+                    FormattedPart::Field(ref expression, _, _) => match **expression {
+                        Expression::Tuple(ref items) => assert_eq!(items.len(), length),
+                        ref other => panic!("{}: {:?}", source, other),
+                    },
+                    ref other => panic!("{}: {:?}", source, other),
+                },
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+        match parse_value("x = f'{yield}'\n") {
+            Expression::Primary(Primary::Atom(Atom::Formatted(_, parts))) => match parts[0] {
+                FormattedPart::Field(ref expression, _, _) => match **expression {
+                    Expression::Primary(Primary::Atom(Atom::Yield(None))) => {}
+                    ref other => panic!("{:?}", other),
+                },
+                ref other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+
+        // A raw backslash doesn't escape the brace after it.
+        match parse_value("x = rf'\\{a}'\n") {
+            Expression::Primary(Primary::Atom(Atom::Formatted(_, parts))) => {
+                assert_eq!(parts.len(), 2);
+                match parts[0] {
+                    FormattedPart::Literal(ref string) => assert_eq!(string, "\\"),
+                    ref other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
         }
-        Ok(())
     }
 }
@@ -0,0 +1,196 @@
+// Nothing in the binary edits a source, relexing is for editor integrations.
+#![allow(dead_code)]
+
+use lexeme::Lexeme;
+use lexer::{indentation, Lexer, LexerError};
+use span::Span;
+
+/// A change to a source, replacing the bytes in `range` with `text`.
+#[derive(Clone, Copy, Debug)]
+pub struct Edit<'e> {
+    pub range: Span,
+    pub text: &'e str,
+}
+
+impl<'e> Edit<'e> {
+    pub fn new(range: Span, text: &'e str) -> Self {
+        Edit {
+            range: range,
+            text: text,
+        }
+    }
+
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = String::with_capacity(source.len() + self.text.len());
+        edited.push_str(&source[..self.range.start]);
+        edited.push_str(self.text);
+        edited.push_str(&source[self.range.end..]);
+        edited
+    }
+
+    /// How far the text after the edit moves.
+    fn delta(&self) -> isize {
+        self.text.len() as isize - self.range.len() as isize
+    }
+}
+
+/// The lexemes of an edited source, and the errors in the lines that were
+/// lexed again.
+#[derive(Debug)]
+pub struct Relexed<'a> {
+    pub lexemes: Vec<Lexeme<'a>>,
+    pub errors: Vec<LexerError<'a>>,
+    /// How many lexemes were lexed again rather than reused.
+    pub relexed: usize,
+}
+
+/// Updates `old`, the lexemes of `old_source`, for `edit`, which turned it
+/// into `source`. Lexing starts again from the logical line before the
+/// edit, and stops at the first line after it that ends where an old line
+/// did inside the same blocks, since from there on the old lexemes are still
+/// right once they're moved along.
+pub fn relex<'a>(old_source: &str, old: &[Lexeme], edit: Edit, source: &'a str) -> Relexed<'a> {
+    let delta = edit.delta();
+
+    // Find the last line that ended before the edit, and the blocks open
+    // after it.
+    let mut indents = vec![(0, 0)];
+    let mut restart = (0, 0, indents.clone());
+    for (index, lexeme) in old.iter().enumerate() {
+        if lexeme.span().end >= edit.range.start {
+            break;
+        }
+        replay(old_source, lexeme, &mut indents);
+        if let Lexeme::Newline(span) = *lexeme {
+            if !span.is_empty() {
+                restart = (index + 1, span.end, indents.clone());
+            }
+        }
+    }
+
+    let (restart, offset, indents) = restart;
+    let mut lexemes: Vec<_> = old[..restart].iter().map(|lexeme| lexeme.moved(0, source)).collect();
+    let mut errors = Vec::new();
+    let mut lexer = Lexer::resume(source, offset, indents.clone());
+
+    // The old lexemes and open blocks, kept level with the new ones.
+    let mut old_index = restart;
+    let mut old_indents = indents;
+
+    while let Some(result) = lexer.next() {
+        let lexeme = match result {
+            Ok(lexeme) => lexeme,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+
+        let end = match lexeme {
+            Lexeme::Newline(span) if !span.is_empty() => Some(span.end),
+            _ => None,
+        };
+        lexemes.push(lexeme);
+
+        let old_end = match end {
+            Some(end) if end as isize - delta >= edit.range.end as isize => {
+                (end as isize - delta) as usize
+            }
+            _ => continue,
+        };
+
+        while old_index < old.len() && old[old_index].span().end <= old_end {
+            replay(old_source, &old[old_index], &mut old_indents);
+            old_index += 1;
+        }
+
+        let in_step = match old_index.checked_sub(1).map(|index| &old[index]) {
+            Some(&Lexeme::Newline(span)) => span.end == old_end,
+            _ => false,
+        };
+
+        if in_step && &*old_indents == lexer.indents() {
+            let relexed = lexemes.len() - restart;
+            lexemes.extend(old[old_index..].iter().map(|lexeme| lexeme.moved(delta, source)));
+            return Relexed {
+                lexemes: lexemes,
+                errors: errors,
+                relexed: relexed,
+            };
+        }
+    }
+
+    let relexed = lexemes.len() - restart;
+    Relexed {
+        lexemes: lexemes,
+        errors: errors,
+        relexed: relexed,
+    }
+}
+
+/// Tracks the blocks opened and closed by `lexeme`.
+fn replay(source: &str, lexeme: &Lexeme, indents: &mut Vec<(usize, usize)>) {
+    match *lexeme {
+        Lexeme::Indent(span) => indents.push(indentation(&source[span.start..span.end])),
+        Lexeme::Dedent(_) => {
+            let _ = indents.pop();
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> Vec<Lexeme> {
+        Lexer::new(source).filter_map(Result::ok).collect()
+    }
+
+    /// Relexes `source` after replacing `from` with `to`, checking it gives
+    /// the same as lexing the edited source from scratch, and returns how
+    /// many lexemes had to be lexed again.
+    fn check(source: &str, from: &str, to: &str) -> usize {
+        let start = source.find(from).unwrap();
+        let edit = Edit::new(Span::new(start, start + from.len()), to);
+        let edited = edit.apply(source);
+
+        let old = lex(source);
+        let relexed = relex(source, &old, edit, &edited);
+        let expected = lex(&edited);
+
+        assert_eq!(format!("{:?}", relexed.lexemes), format!("{:?}", expected),
+                   "{:?} -> {:?}", from, to);
+        relexed.relexed
+    }
+
+    #[test]
+    fn matches_a_full_lex() {
+        let source = "import os\n\
+                      def f(a):\n    \
+                          x = f'{a!r}' + 1\n    \
+                          if x:\n        \
+                              return [x,\n                \
+                                      2]\n    \
+                          return None\n\
+                      \n\
+                      y = f(1)\n\
+                      z = 'end'\n";
+
+        // Only the edited line is lexed again, with the indent or dedent
+        // at its start.
+        assert_eq!(check(source, "x = f'", "xs = f'"), 7);
+        assert_eq!(check(source, "y = f(1)", "y = f(12345)"), 8);
+
+        check(source, "import os\n", "");
+        check(source, "if x:\n        ", "if x:\n            ");
+        check(source, "    return None\n", "");
+        check(source, "return [x,", "return [x, (");
+        check(source, "'end'", "'''end\n'''");
+        check(source, "'end'\n", "'end'");
+        check(source, "z", "\tz");
+        check(source, "\ny = ", "\n  y = ");
+        check(source, "def", "async def");
+        check(source, "\n", "\r\n");
+    }
+}
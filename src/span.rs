@@ -0,0 +1,156 @@
+use std::fmt;
+
+/// A half open range of byte offsets into the source, `start..end`.
+#[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span {
+            start: start,
+            end: end,
+        }
+    }
+
+    /// A zero width span, used for tokens that have no text of their own.
+    pub fn empty(at: usize) -> Self {
+        Span::new(at, at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The span moved `delta` bytes along.
+    pub fn shift(self, delta: isize) -> Self {
+        let shift = |offset: usize| (offset as isize + delta) as usize;
+        Span::new(shift(self.start), shift(self.end))
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Self {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A human readable location. Lines start at one, columns are counted in
+/// characters starting at zero.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Byte offsets of the start of every line in a source, so that spans can be
+/// turned into line and column positions.
+#[derive(Clone, Debug)]
+pub struct LineTable<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineTable<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let bytes = source.as_bytes();
+        let mut starts = vec![0];
+
+        for (index, &byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' => starts.push(index + 1),
+                b'\r' if bytes.get(index + 1) != Some(&b'\n') => {
+                    starts.push(index + 1)
+                }
+                _ => {}
+            }
+        }
+
+        LineTable {
+            source: source,
+            starts: starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The byte offset where `line` (starting at one) begins.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line.wrapping_sub(1)).cloned()
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column = self.source[self.starts[line]..offset].chars().count();
+
+        Position {
+            line: line + 1,
+            column: column,
+        }
+    }
+
+    pub fn span_position(&self, span: Span) -> (Position, Position) {
+        (self.position(span.start), self.position(span.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, column: usize) -> Position {
+        Position {
+            line: line,
+            column: column,
+        }
+    }
+
+    #[test]
+    fn line_table() {
+        // A CRLF ends one line, a lone CR another, and `é` is two bytes.
+        let lines = LineTable::new("a\r\nb\rc\u{E9}d\n");
+        assert_eq!(lines.line_count(), 4);
+
+        assert_eq!(lines.line_start(0), None);
+        assert_eq!(lines.line_start(1), Some(0));
+        assert_eq!(lines.line_start(2), Some(3));
+        assert_eq!(lines.line_start(3), Some(5));
+        assert_eq!(lines.line_start(4), Some(10));
+        assert_eq!(lines.line_start(5), None);
+
+        assert_eq!(lines.position(0), at(1, 0));
+        assert_eq!(lines.position(2), at(1, 2));
+        assert_eq!(lines.position(3), at(2, 0));
+        assert_eq!(lines.position(4), at(2, 1));
+        assert_eq!(lines.position(5), at(3, 0));
+        assert_eq!(lines.position(8), at(3, 2));
+        assert_eq!(lines.position(10), at(4, 0));
+        // Offsets past the end are clamped to it.
+        assert_eq!(lines.position(100), at(4, 0));
+
+        assert_eq!(lines.span_position(Span::new(3, 6)), (at(2, 0), at(3, 1)));
+    }
+}
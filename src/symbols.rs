@@ -1,4 +1,5 @@
 pub const ESCAPE: char = '\\';
-pub const NEWLINE: char = '\r';
-pub const CARRIAGE: char = '\n';
-pub const FORMFEED: char = '\u{21A1}';
+pub const NEWLINE: char = '\n';
+pub const CARRIAGE: char = '\r';
+pub const FORMFEED: char = '\u{C}';
+pub const TAB_SIZE: usize = 8;
@@ -0,0 +1,212 @@
+use std::fmt::Write;
+
+use encoding::Encoding;
+use lexeme::Lexeme;
+use lossless::{Lossless, Trivia};
+use span::{LineTable, Position, Span};
+
+/// Writes the tokens of `source` the way `python -m tokenize` prints them,
+/// one per line with their position, type and text, so the two can be
+/// diffed. This follows Python 3.11, where an f-string is a single `STRING`
+/// rather than being split into its parts. `encoding` is the name to give
+/// the leading `ENCODING` token.
+pub fn dump(source: &str, encoding: &str) -> String {
+    let lines = LineTable::new(source);
+    let mut output = String::new();
+    write_token(&mut output, "ENCODING", (Position { line: 0, column: 0 },
+                                          Position { line: 0, column: 0 }), encoding);
+
+    // Python lexes a file that doesn't end in a newline as if it did, so the
+    // last `NEWLINE` is one character long and what follows it is on a line
+    // of its own.
+    let unterminated = !source.is_empty() && !source.ends_with('\n') && !source.ends_with('\r');
+    let after_end = Position { line: lines.line_count() + 1, column: 0 };
+    let mut last = "";
+
+    for token in Lossless::new(source) {
+        let token = match token {
+            Ok(token) => token,
+            // The text the error was for is still yielded, as trivia.
+            Err(_) => continue,
+        };
+
+        for trivia in &token.leading {
+            let kind = match *trivia {
+                Trivia::Comment(_) => "COMMENT",
+                Trivia::Newline(_) => "NL",
+                Trivia::Skipped(_) => "ERRORTOKEN",
+                Trivia::Continuation(_) | Trivia::Whitespace(_) => continue,
+            };
+            let span = trivia.span();
+            let text = &source[span.start..span.end];
+            write_token(&mut output, kind, positions(&lines, span, text), text);
+            last = kind;
+        }
+
+        let span = token.lexeme.span();
+        let text = &source[span.start..span.end];
+        let kind = kind(&token.lexeme);
+        let mut range = positions(&lines, span, text);
+
+        if unterminated && span.start == source.len() {
+            match token.lexeme {
+                Lexeme::Newline(_) => range.1.column += 1,
+                _ => {
+                    if last == "COMMENT" {
+                        write_token(&mut output, "NL", range, "");
+                    }
+                    range = (after_end, after_end);
+                }
+            }
+        }
+
+        write_token(&mut output, kind, range, text);
+        last = kind;
+    }
+
+    output
+}
+
+/// The start and end of `span` as `tokenize` gives them. The end of a
+/// newline is on the line it ends, not at the start of the next.
+fn positions(lines: &LineTable, span: Span, text: &str) -> (Position, Position) {
+    let (start, mut end) = lines.span_position(span);
+    let newline = text.len() - text.trim_end_matches(|ch| ch == '\n' || ch == '\r').len();
+    if newline > 0 {
+        end = lines.position(span.end - newline);
+        end.column += newline;
+    }
+    (start, end)
+}
+
+fn write_token(output: &mut String, kind: &str, range: (Position, Position), text: &str) {
+    let (start, end) = range;
+    let range = format!("{},{}-{},{}:", start.line, start.column, end.line, end.column);
+    let _ = writeln!(output, "{:<20}{:<15}{:<15}", range, kind, repr(text));
+}
+
+/// The name `tokenize` gives an encoding. It doesn't tell apart UTF-8 with
+/// and without a BOM.
+pub fn encoding_name(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Utf8 => "utf-8",
+        Encoding::Latin1 => "iso-8859-1",
+        Encoding::Ascii => "ascii",
+    }
+}
+
+fn kind(lexeme: &Lexeme) -> &'static str {
+    match *lexeme {
+        Lexeme::Bytes(..) | Lexeme::FormattedStr(..) | Lexeme::Str(..) => "STRING",
+        Lexeme::Dedent(_) => "DEDENT",
+        Lexeme::Delimiter(..) | Lexeme::Operator(..) => "OP",
+        Lexeme::EndMarker(_) => "ENDMARKER",
+        Lexeme::Float(..) | Lexeme::Imaginary(..) | Lexeme::Integer(..) => "NUMBER",
+        Lexeme::Identifier(..) | Lexeme::Keyword(..) => "NAME",
+        Lexeme::Indent(_) => "INDENT",
+        Lexeme::Newline(_) => "NEWLINE",
+    }
+}
+
+/// Python's `repr` of a `str`.
+fn repr(text: &str) -> String {
+    let quote = if text.contains('\'') && !text.contains('"') { '"' } else { '\'' };
+    let mut repr = String::new();
+    repr.push(quote);
+
+    for ch in text.chars() {
+        match ch {
+            '\\' => repr.push_str("\\\\"),
+            '\n' => repr.push_str("\\n"),
+            '\r' => repr.push_str("\\r"),
+            '\t' => repr.push_str("\\t"),
+            ch if ch == quote => {
+                repr.push('\\');
+                repr.push(ch);
+            }
+            ch if ch.is_control() || (ch.is_whitespace() && ch != ' ') => {
+                let _ = match ch as u32 {
+                    code if code <= 0xFF => write!(repr, "\\x{:02x}", code),
+                    code if code <= 0xFFFF => write!(repr, "\\u{:04x}", code),
+                    code => write!(repr, "\\U{:08x}", code),
+                };
+            }
+            ch => repr.push(ch),
+        }
+    }
+
+    repr.push(quote);
+    repr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_cpython() {
+        let source = "if a:\n    # note\n    b = \"x\\n\" + 1\n\n  # trailing\nc = (1,\n     2)\n";
+        // The output of `python3.11 -m tokenize` for the same source.
+        let expected = "\
+0,0-0,0:            ENCODING       'utf-8'        
+1,0-1,2:            NAME           'if'           
+1,3-1,4:            NAME           'a'            
+1,4-1,5:            OP             ':'            
+1,5-1,6:            NEWLINE        '\\n'           
+2,4-2,10:           COMMENT        '# note'       
+2,10-2,11:          NL             '\\n'           
+3,0-3,4:            INDENT         '    '         
+3,4-3,5:            NAME           'b'            
+3,6-3,7:            OP             '='            
+3,8-3,13:           STRING         '\"x\\\\n\"'       
+3,14-3,15:          OP             '+'            
+3,16-3,17:          NUMBER         '1'            
+3,17-3,18:          NEWLINE        '\\n'           
+4,0-4,1:            NL             '\\n'           
+5,2-5,12:           COMMENT        '# trailing'   
+5,12-5,13:          NL             '\\n'           
+6,0-6,0:            DEDENT         ''             
+6,0-6,1:            NAME           'c'            
+6,2-6,3:            OP             '='            
+6,4-6,5:            OP             '('            
+6,5-6,6:            NUMBER         '1'            
+6,6-6,7:            OP             ','            
+6,7-6,8:            NL             '\\n'           
+7,5-7,6:            NUMBER         '2'            
+7,6-7,7:            OP             ')'            
+7,7-7,8:            NEWLINE        '\\n'           
+8,0-8,0:            ENDMARKER      ''             
+";
+        assert_eq!(dump(source, "utf-8"), expected);
+    }
+
+    #[test]
+    fn unterminated_last_line() {
+        let expected = "\
+0,0-0,0:            ENCODING       'utf-8'        
+1,0-1,2:            NAME           'if'           
+1,3-1,4:            NAME           'a'            
+1,4-1,5:            OP             ':'            
+1,5-1,6:            NEWLINE        '\\n'           
+2,0-2,2:            INDENT         '  '           
+2,2-2,3:            NAME           'b'            
+2,3-2,4:            NEWLINE        '\\n'           
+3,2-3,5:            COMMENT        '# c'          
+3,5-3,5:            NL             ''             
+4,0-4,0:            DEDENT         ''             
+4,0-4,0:            ENDMARKER      ''             
+";
+        assert_eq!(dump("if a:\n  b\n  # c", "utf-8"), expected);
+
+        let output = dump("x = 1", "utf-8");
+        assert!(output.ends_with("1,5-1,6:            NEWLINE        ''             \n\
+                                  2,0-2,0:            ENDMARKER      ''             \n"));
+    }
+
+    #[test]
+    fn reprs() {
+        assert_eq!(repr("it's"), "\"it's\"");
+        assert_eq!(repr("'\""), "'\\'\"'");
+        assert_eq!(repr("caf\u{E9}\u{0}\u{85}"), "'caf\u{E9}\\x00\\x85'");
+    }
+}
@@ -1,4 +1,10 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Num, ToPrimitive};
 
 pub enum Object<'a> {
     None,
@@ -14,13 +20,328 @@ pub enum Object<'a> {
 pub enum Number {
     Integral(Integral),
     Real(f64),
+    Complex(Complex),
+}
+
+// Only the tests use this until there's an evaluator to do arithmetic.
+#[allow(dead_code)]
+impl Number {
+    /// Widens any number to a complex one, as Python does when a complex
+    /// number meets another number in an arithmetic operation. `None` if an
+    /// integer is too big to be a float.
+    pub fn to_complex(&self) -> Option<Complex> {
+        match *self {
+            Number::Integral(Integral::Integer(ref integer)) => {
+                integer.to_f64().map(Complex::from)
+            }
+            Number::Integral(Integral::Bool(boolean)) => {
+                Some(Complex::from(if boolean { 1.0 } else { 0.0 }))
+            }
+            Number::Real(real) => Some(Complex::from(real)),
+            Number::Complex(complex) => Some(complex),
+        }
+    }
+}
+
+/// A complex number, held as a pair of floats like CPython does. Division
+/// and powers follow CPython's algorithms so results match to the last bit.
+// Nothing evaluates expressions yet, so complex arithmetic is only reached
+// from the tests.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Complex {
+    pub real: f64,
+    pub imag: f64,
+}
+
+#[allow(dead_code)]
+impl Complex {
+    pub fn new(real: f64, imag: f64) -> Self {
+        Complex {
+            real: real,
+            imag: imag,
+        }
+    }
+
+    pub fn abs(self) -> f64 {
+        self.real.hypot(self.imag)
+    }
+
+    pub fn conjugate(self) -> Self {
+        Complex::new(self.real, -self.imag)
+    }
+
+    pub fn div(self, other: Self) -> Result<Self, ZeroDivisionError> {
+        self.quotient(other)
+            .ok_or(ZeroDivisionError("complex division by zero"))
+    }
+
+    pub fn pow(self, exponent: Self) -> Result<Self, ZeroDivisionError> {
+        const ERROR: ZeroDivisionError =
+            ZeroDivisionError("0.0 to a negative or complex power");
+
+        // Small whole exponents are done by repeated multiplication, which is
+        // more accurate than going through polar form.
+        if exponent.imag == 0.0 && exponent.real == exponent.real.floor() &&
+           exponent.real.abs() <= 100.0 {
+            let power = exponent.real as i64;
+            return if power >= 0 {
+                Ok(self.powu(power as u64))
+            } else {
+                Complex::from(1.0).quotient(self.powu(-power as u64)).ok_or(ERROR)
+            };
+        }
+
+        if exponent.real == 0.0 && exponent.imag == 0.0 {
+            return Ok(Complex::from(1.0));
+        }
+
+        if self.real == 0.0 && self.imag == 0.0 {
+            return if exponent.imag != 0.0 || exponent.real < 0.0 {
+                Err(ERROR)
+            } else {
+                Ok(Complex::from(0.0))
+            };
+        }
+
+        let abs = self.abs();
+        let angle = self.imag.atan2(self.real);
+        let mut length = abs.powf(exponent.real);
+        let mut phase = angle * exponent.real;
+
+        if exponent.imag != 0.0 {
+            length /= (angle * exponent.imag).exp();
+            phase += exponent.imag * abs.ln();
+        }
+
+        Ok(Complex::new(length * phase.cos(), length * phase.sin()))
+    }
+
+    /// Smith's algorithm, `None` when dividing by zero.
+    fn quotient(self, other: Self) -> Option<Self> {
+        let abs_real = other.real.abs();
+        let abs_imag = other.imag.abs();
+
+        if abs_real >= abs_imag {
+            if abs_real == 0.0 {
+                return None;
+            }
+            let ratio = other.imag / other.real;
+            let denominator = other.real + other.imag * ratio;
+            Some(Complex::new((self.real + self.imag * ratio) / denominator,
+                              (self.imag - self.real * ratio) / denominator))
+        } else if abs_imag >= abs_real {
+            let ratio = other.real / other.imag;
+            let denominator = other.real * ratio + other.imag;
+            Some(Complex::new((self.real * ratio + self.imag) / denominator,
+                              (self.imag * ratio - self.real) / denominator))
+        } else {
+            // One of the parts is NaN.
+            Some(Complex::new(::std::f64::NAN, ::std::f64::NAN))
+        }
+    }
+
+    fn powu(self, mut power: u64) -> Self {
+        let mut result = Complex::from(1.0);
+        let mut base = self;
+
+        while power > 0 {
+            if power & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            power >>= 1;
+        }
+        result
+    }
 }
 
+impl From<f64> for Complex {
+    fn from(real: f64) -> Self {
+        Complex::new(real, 0.0)
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Complex::new(self.real + other.real, self.imag + other.imag)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Complex::new(self.real - other.real, self.imag - other.imag)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        Complex::new(self.real * other.real - self.imag * other.imag,
+                     self.real * other.imag + self.imag * other.real)
+    }
+}
+
+impl ops::Neg for Complex {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Complex::new(-self.real, -self.imag)
+    }
+}
+
+/// Python's `ZeroDivisionError`, with its message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ZeroDivisionError(pub &'static str);
+
 pub enum Integral {
-    Integer(i64),
+    Integer(Int),
     Bool(bool),
 }
 
+/// A Python `int`. Anything that fits in an `i64` is kept as one, and only
+/// values outside that range fall back to a `BigInt`, so arithmetic on small
+/// numbers never allocates.
+#[derive(Clone, Eq, Hash, PartialEq)]
+pub enum Int {
+    Small(i64),
+    Big(BigInt),
+}
+
+impl Int {
+    /// Parses unsigned digits, without a prefix or underscores.
+    pub fn from_str_radix(digits: &str, radix: u32) -> Option<Self> {
+        if digits.starts_with('+') || digits.starts_with('-') {
+            return None;
+        }
+
+        match i64::from_str_radix(digits, radix) {
+            Ok(small) => Some(Int::Small(small)),
+            Err(_) => BigInt::from_str_radix(digits, radix).ok().map(Int::from),
+        }
+    }
+
+    /// `None` if the integer is too big to be a float, where Python raises
+    /// an `OverflowError`.
+    pub fn to_f64(&self) -> Option<f64> {
+        match *self {
+            Int::Small(small) => Some(small as f64),
+            Int::Big(ref big) => big.to_f64().and_then(|float| {
+                if float.is_finite() { Some(float) } else { None }
+            }),
+        }
+    }
+
+    pub fn to_big(&self) -> BigInt {
+        match *self {
+            Int::Small(small) => BigInt::from(small),
+            Int::Big(ref big) => big.clone(),
+        }
+    }
+
+    pub fn pow(&self, exponent: u32) -> Self {
+        if let Int::Small(small) = *self {
+            if let Some(result) = small.checked_pow(exponent) {
+                return Int::Small(result);
+            }
+        }
+        Int::from(self.to_big().pow(exponent))
+    }
+
+    /// Division rounding towards negative infinity, like Python's `//`.
+    pub fn div_floor(&self, other: &Self) -> Result<Self, ZeroDivisionError> {
+        if other.is_zero() {
+            return Err(ZeroDivisionError("integer division or modulo by zero"));
+        }
+
+        if let (&Int::Small(lhs), &Int::Small(rhs)) = (self, other) {
+            if let Some(_) = lhs.checked_div(rhs) {
+                return Ok(Int::Small(Integer::div_floor(&lhs, &rhs)));
+            }
+        }
+        Ok(Int::from(self.to_big().div_floor(&other.to_big())))
+    }
+
+    /// The remainder taking the sign of the divisor, like Python's `%`.
+    pub fn mod_floor(&self, other: &Self) -> Result<Self, ZeroDivisionError> {
+        if other.is_zero() {
+            return Err(ZeroDivisionError("integer division or modulo by zero"));
+        }
+
+        if let (&Int::Small(lhs), &Int::Small(rhs)) = (self, other) {
+            if let Some(_) = lhs.checked_rem(rhs) {
+                return Ok(Int::Small(Integer::mod_floor(&lhs, &rhs)));
+            }
+        }
+        Ok(Int::from(self.to_big().mod_floor(&other.to_big())))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Int::Small(0)
+    }
+}
+
+impl From<i64> for Int {
+    fn from(small: i64) -> Self {
+        Int::Small(small)
+    }
+}
+
+impl From<BigInt> for Int {
+    fn from(big: BigInt) -> Self {
+        match big.to_i64() {
+            Some(small) => Int::Small(small),
+            None => Int::Big(big),
+        }
+    }
+}
+
+macro_rules! int_op {
+    ($trait_name:ident, $method:ident, $checked:ident) => {
+        impl ops::$trait_name for Int {
+            type Output = Int;
+            fn $method(self, other: Int) -> Int {
+                if let (&Int::Small(lhs), &Int::Small(rhs)) = (&self, &other) {
+                    if let Some(result) = lhs.$checked(rhs) {
+                        return Int::Small(result);
+                    }
+                }
+                Int::from(ops::$trait_name::$method(self.to_big(), other.to_big()))
+            }
+        }
+    }
+}
+
+int_op!(Add, add, checked_add);
+int_op!(Sub, sub, checked_sub);
+int_op!(Mul, mul, checked_mul);
+
+impl ops::Neg for Int {
+    type Output = Int;
+    fn neg(self) -> Int {
+        match self {
+            Int::Small(small) if small != i64::min_value() => Int::Small(-small),
+            other => Int::from(-other.to_big()),
+        }
+    }
+}
+
+impl fmt::Display for Int {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Int::Small(small) => write!(f, "{}", small),
+            Int::Big(ref big) => write!(f, "{}", big),
+        }
+    }
+}
+
+impl fmt::Debug for Int {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 pub enum Sequence<'a> {
     Immutable(ImmutableSequence<'a>),
     Mutable(MutableSequence<'a>),
@@ -49,3 +370,46 @@ pub enum Map<'a> {
 pub enum Callable<'a> {
     User(&'a str, HashMap<&'a str, Object<'a>>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn int_arithmetic() {
+        let two = Int::from(2);
+        let big = two.pow(100);
+        assert_eq!(big.to_string(), "1267650600228229401496703205376");
+        assert_eq!(big.clone() - big.clone(), Int::Small(0));
+
+        let max = Int::from(i64::max_value());
+        assert_eq!((max.clone() + Int::from(1)).to_string(), "9223372036854775808");
+        assert_eq!(-Int::from(i64::min_value()), max.clone() + Int::from(1));
+
+        let literal = "1234567890123456789012345678901234567890";
+        assert_eq!(Int::from_str_radix(literal, 10).unwrap().to_string(), literal);
+
+        assert_eq!(Int::from(-7).div_floor(&Int::from(2)), Ok(Int::from(-4)));
+        assert_eq!(Int::from(-7).mod_floor(&Int::from(2)), Ok(Int::from(1)));
+        assert_eq!(Int::from(i64::min_value()).div_floor(&Int::from(-1)),
+                   Ok(max + Int::from(1)));
+        assert!(big.mod_floor(&Int::from(0)).is_err());
+    }
+
+    #[test]
+    fn complex_arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+
+        assert_eq!(a.div(Complex::new(3.0, -4.0)), Ok(Complex::new(-0.2, 0.4)));
+        assert_eq!(Complex::new(1.0, 1.0).pow(Complex::from(3.0)),
+                   Ok(Complex::new(-2.0, 2.0)));
+        assert_eq!(Complex::new(2.0, 0.0).pow(Complex::from(-2.0)),
+                   Ok(Complex::new(0.25, 0.0)));
+        assert_eq!(a.pow(Complex::new(0.5, 1.0)),
+                   Ok(Complex::new(0.1042330549168707, 0.48309593926990946)));
+
+        assert!(a.div(Complex::from(0.0)).is_err());
+        assert!(Complex::from(0.0).pow(Complex::from(-1.0)).is_err());
+        assert_eq!(Complex::from(0.0).pow(Complex::from(0.0)), Ok(Complex::from(1.0)));
+    }
+}
@@ -0,0 +1,72 @@
+use std::char;
+
+// Defines `NAMES`, sorted by name, and `IDEOGRAPHS`, the blocks whose names
+// are generated from their code points.
+include!(concat!(env!("OUT_DIR"), "/unicode_names.rs"));
+
+const HANGUL_PREFIX: &'static str = "HANGUL SYLLABLE ";
+const HANGUL_BASE: u32 = 0xAC00;
+const HANGUL_LEADS: [&'static str; 19] = [
+    "G", "GG", "N", "D", "DD", "R", "M", "B", "BB", "S", "SS", "", "J", "JJ",
+    "C", "K", "T", "P", "H",
+];
+const HANGUL_VOWELS: [&'static str; 21] = [
+    "A", "AE", "YA", "YAE", "EO", "E", "YEO", "YE", "O", "WA", "WAE", "OE",
+    "YO", "U", "WEO", "WE", "WI", "YU", "EU", "YI", "I",
+];
+const HANGUL_TAILS: [&'static str; 28] = [
+    "", "G", "GG", "GS", "N", "NJ", "NH", "D", "L", "LG", "LM", "LB", "LS",
+    "LT", "LP", "LH", "M", "B", "BS", "S", "SS", "NG", "J", "C", "K", "T",
+    "P", "H",
+];
+
+/// Finds the character with the given Unicode name, ignoring case like
+/// Python's `\N{...}` escapes do.
+pub fn lookup(name: &str) -> Option<char> {
+    let name = name.to_uppercase();
+
+    if let Ok(index) = NAMES.binary_search_by(|&(key, _)| key.cmp(&name)) {
+        return char::from_u32(NAMES[index].1);
+    }
+
+    if name.starts_with(HANGUL_PREFIX) {
+        return hangul_syllable(&name[HANGUL_PREFIX.len()..]);
+    }
+
+    for &(prefix, first, last) in IDEOGRAPHS {
+        if !name.starts_with(prefix) {
+            continue;
+        }
+
+        let digits = &name[prefix.len()..];
+        if digits.len() < 4 || digits.len() > 5 {
+            return None;
+        }
+
+        match u32::from_str_radix(digits, 16) {
+            Ok(code) if code >= first && code <= last => return char::from_u32(code),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Hangul syllable names are built from the names of their jamo. Leads are
+/// all consonants and vowels all start with a vowel, so the name can be
+/// split without backtracking.
+fn hangul_syllable(name: &str) -> Option<char> {
+    let vowel_start = name.find(|c| "AEIOUWY".contains(c)).unwrap_or(name.len());
+    let tail_start = name[vowel_start..]
+        .find(|c| !"AEIOUWY".contains(c))
+        .map_or(name.len(), |index| vowel_start + index);
+
+    let lead = HANGUL_LEADS.iter().position(|&l| l == &name[..vowel_start])?;
+    let vowel = HANGUL_VOWELS.iter()
+                             .position(|&v| v == &name[vowel_start..tail_start])?;
+    let tail = HANGUL_TAILS.iter().position(|&t| t == &name[tail_start..])?;
+
+    let code = (lead as u32 * HANGUL_VOWELS.len() as u32 + vowel as u32) *
+               HANGUL_TAILS.len() as u32 + tail as u32;
+    char::from_u32(HANGUL_BASE + code)
+}
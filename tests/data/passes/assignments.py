@@ -0,0 +1,12 @@
+first = second = 0
+head, *tail = items
+(a, b), c = pairs
+[x, y] = point
+self.count += 1
+totals[key] -= value
+mask &= ~flag
+power **= 2
+quotient //= divisor
+name: str = "rython"
+pending: list
+self.cache: dict = {}
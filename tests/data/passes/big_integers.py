@@ -0,0 +1,2 @@
+big = 1234567890123456789012345678901234567890
+bigger_hex = 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF
@@ -0,0 +1,3 @@
+header = b"\xff\xfe\x00\x01"
+magic = b"\211PNG\r\n\032\n"
+raw = rb"\d+"
@@ -0,0 +1,9 @@
+print("hi")
+print("a", "b", sep=", ", end="\n")
+result = function(*args, **kwargs)
+value = matrix[1:2:3, ...]
+row = matrix[0][:]
+tail = items[1:]
+every_other = items[::2]
+name = self.value.name
+handler(first)(second)
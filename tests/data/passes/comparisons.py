@@ -0,0 +1,7 @@
+in_range = 0 <= index < length
+different = a != b != c
+missing = key not in mapping and value is not None
+either = first or second or third
+neither = not first and not second
+if x in items or y is None:
+    pass
@@ -0,0 +1,4 @@
+imaginary = 1j
+real_and_imaginary = 2.5J
+zero = 0j
+fraction = .5j
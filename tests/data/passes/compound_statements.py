@@ -0,0 +1,41 @@
+if ready:
+    start = True
+elif waiting:
+    pass
+else:
+    start = False
+
+while running:
+    if done: break
+    continue
+else:
+    finished = True
+
+for key, value in items:
+    # Comments and blank lines inside blocks are skipped.
+
+    total = value
+else:
+    total = None
+
+try:
+    import json
+except ImportError as error:
+    json = None
+except:
+    raise
+else:
+    loaded = True
+finally:
+    cleanup = True
+
+try:
+    pass
+except* ValueError:
+    pass
+
+with open_file as handle, lock:
+    data = handle
+with (first as one,
+      second as two):
+    pass
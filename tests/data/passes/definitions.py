@@ -0,0 +1,23 @@
+@staticmethod
+def greet(name: str, greeting: str = "Hello", /, *, punctuation="!") -> str:
+    return greeting
+
+
+def collect(first, *rest, **options):
+    pass
+
+
+def nothing():
+    pass
+
+
+class Base:
+    pass
+
+
+class Derived(Base, metaclass=Meta):
+    @property
+    def value(self):
+        return self
+
+    class Nested(*bases, **options): pass
@@ -0,0 +1,19 @@
+empty = ()
+single = (1,)
+pair = (1, 2)
+grouped = (value)
+numbers = [1, 2, 3,]
+nested = [[1, 2], [3, 4]]
+unique = {1, 2, *others}
+mapping = {"a": 1, "b": 2, **defaults}
+nothing = {}
+squares = [x for x in numbers if x]
+pairs = {key: value for key, value in items}
+letters = {letter for word in words for letter in word}
+lazy = (line for line in lines)
+total = sum(x for x in numbers)
+first, *rest = numbers
+
+
+async def gather(stream):
+    return [item async for item in stream]
@@ -0,0 +1,9 @@
+mode = "debug" if verbose else "release"
+level = 0 if quiet else 1 if normal else 2
+key = lambda item: item.name
+default = lambda *args, **kwargs: None
+if (count := len(items)) > 10:
+    pass
+while line := read():
+    process(line)
+filtered = [clean for raw in lines if (clean := raw.strip())]
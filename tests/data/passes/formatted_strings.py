@@ -0,0 +1,8 @@
+name = "World"
+greeting = f"Hello {name}!"
+escaped = f"{{literal}} {name!r}"
+spec = f"{3.14159:.2f}"
+nested = f"{name:>{10}}"
+joined = "Hello " f"{name}" "!"
+quoted = f"{'}'}"
+raw = rf"\d{name}"
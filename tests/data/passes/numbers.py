@@ -0,0 +1,7 @@
+mask = 0xFFFF_0000
+flags = 0b_1010_0101
+mode = 0o755
+million = 1_000_000
+half = .5
+whole = 1.
+small = 1_0.0_1e-1_0
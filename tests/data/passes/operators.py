@@ -0,0 +1,7 @@
+total = a + b + c - d
+scaled = a * b / c // d % e
+product = matrix @ vector
+mask = flags & 0xFF | extra ^ toggle
+shifted = value << 2 >> 1
+power = 2 ** -1 ** 2
+negated = -x ** 2 + ~y
@@ -0,0 +1,14 @@
+import os.path as path, sys
+from . import sibling
+from ..package.module import (first as one, second,)
+from __future__ import *
+
+global counter
+nonlocal total, count
+assert counter, "counter must be set"
+del counter, total
+pass; x = 1; y = None;
+raise ValueError from None
+return x, y
+break
+continue
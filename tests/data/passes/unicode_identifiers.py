@@ -0,0 +1,3 @@
+ﬁle = "ligature"
+café = file
+π = 3.14159
@@ -0,0 +1,6 @@
+letter = "\N{LATIN SMALL LETTER A}"
+lower = "\N{latin capital letter a with grave}"
+emoji = "\N{PILE OF POO}"
+hangul = "\N{HANGUL SYLLABLE GAG}"
+ideograph = "\N{CJK UNIFIED IDEOGRAPH-4E00}"
+formatted = f"\N{EM DASH}{letter}"
@@ -0,0 +1,17 @@
+def generator(values):
+    yield
+    yield 1
+    yield 1, 2
+    yield *values, 3
+    yield from values
+    x = yield
+    x = y = yield values
+    x += yield 1
+    x: int = yield
+    print((yield))
+    return (yield from generator(values))
+
+
+async def agenerator():
+    value = (yield 1)
+    lambda: (yield)